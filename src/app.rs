@@ -1,14 +1,32 @@
-use crate::components::{
-    multi_select_list::{DefaultSelection, MultiSelectList},
-    select_list::SelectList,
+use crate::{
+    components::{
+        multi_select_list::{DefaultSelection, MultiSelectList},
+        range_inputs::RangeInputs,
+        select_list::SelectList,
+    },
+    config::Config,
+    core::{
+        api::{GameModes, RankStatus, API},
+        beatmap::{BeatmapList, BeatmapSet},
+        filter::{Filters, Range},
+        library::Library,
+        mirror::Variant,
+        osz::OszInfo,
+        query::{ListMode, ListQuery},
+        search::{Field, SearchQuery},
+    },
+    download::{history::History, DownloadEvent},
 };
+use clap::ValueEnum;
 use ratatui::{
     layout::Alignment,
     style::{Color, Style},
     text::Line,
     widgets::{Block, BorderType, Borders},
 };
+use std::{collections::HashSet, sync::Arc, time::Instant};
 use time::{Date, OffsetDateTime};
+use tokio_util::sync::CancellationToken;
 use tui_textarea::TextArea;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -16,9 +34,100 @@ pub enum Page {
     Help,
     #[default]
     Main,
+    Results,
+    Downloads,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadState {
+    Queued,
+    Downloading,
+    Verifying,
+    Done,
+    /// Already in the library.
+    Skipped,
+    Failed,
+}
+
+/// One beatmap set on the downloads page.
+#[derive(Debug, Clone)]
+pub struct DownloadItem {
+    pub sid: u32,
+    pub title: String,
+    pub state: DownloadState,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    /// Bytes per second.
+    pub speed: f64,
+    /// Time and bytes of the last progress, to calculate the speed.
+    last: Option<(Instant, u64)>,
+    pub error: Option<String>,
+    /// Difficulties of the downloaded `.osz`.
+    pub info: Option<Box<OszInfo>>,
+}
+
+impl DownloadItem {
+    pub fn new(sid: u32, title: String) -> Self {
+        Self {
+            sid,
+            title,
+            state: DownloadState::Queued,
+            downloaded: 0,
+            total: None,
+            speed: 0.0,
+            last: None,
+            error: None,
+            info: None,
+        }
+    }
+
+    /// Downloaded ratio in [0, 1].
+    pub fn ratio(&self) -> f64 {
+        match (self.state, self.total) {
+            (DownloadState::Verifying | DownloadState::Done | DownloadState::Skipped, _) => 1.0,
+            (_, Some(total)) if total > 0 => (self.downloaded as f64 / total as f64).min(1.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Estimated seconds to finish.
+    pub fn eta(&self) -> Option<u64> {
+        let rest = self.total?.saturating_sub(self.downloaded);
+        (self.speed > 0.0).then(|| (rest as f64 / self.speed) as u64)
+    }
+
+    fn progress(&mut self, downloaded: u64, total: Option<u64>) {
+        let now = Instant::now();
+        match self.last {
+            Some((time, bytes)) if downloaded >= bytes => {
+                let secs = now.duration_since(time).as_secs_f64();
+                if secs > 0.0 {
+                    let speed = (downloaded - bytes) as f64 / secs;
+                    // smooth the speed to avoid jumping
+                    self.speed = if self.speed == 0.0 {
+                        speed
+                    } else {
+                        self.speed * 0.7 + speed * 0.3
+                    };
+                }
+            }
+            _ => self.speed = 0.0,
+        }
+        self.last = Some((now, downloaded));
+        self.state = DownloadState::Downloading;
+        self.downloaded = downloaded;
+        self.total = total;
+    }
+}
+
+const SESSION_MAX: usize = 6;
+/// Indices of the set bits of `bits`, the selection of a list of flags.
+fn bit_indices(bits: u32) -> Vec<usize> {
+    (0..u32::BITS as usize)
+        .filter(|i| bits & 1 << i != 0)
+        .collect()
 }
 
-const SESSION_MAX: usize = 4;
 pub const DEFAULT_BLOCK: Block<'_> = Block::new()
     .title_alignment(Alignment::Center)
     .borders(Borders::ALL)
@@ -30,17 +139,59 @@ pub struct App<'a> {
     /// Layer of the app. If `layer` = 0, app would quit.
     pub layer: u8,
     pub force_quit: bool,
+    /// Cancelled on force quit, stopping all in-flight downloads.
+    pub cancel: CancellationToken,
     /// beatmap type: after a day / hotest / newest / search
     pub mode: SelectList<'a>,
     pub item: MultiSelectList<'a>,
     pub map_state: MultiSelectList<'a>,
+    /// Download variant: full / no video / mini
+    pub variant: SelectList<'a>,
+    /// Ranges of [`Filters`] in its order.
+    pub filters: RangeInputs<'a>,
     pub date: Date,
     pub page: Page,
+    /// Pages to go back to.
+    history: Vec<Page>,
     /// Paragraph (Block) of the current page.
     pub session: usize,
     /// text in mini editor
     pub text: TextArea<'a>, // number
     pub text2: TextArea<'a>, // name
+    /// Why the search in `text2` does not parse, shown in its title.
+    pub search_error: Option<String>,
+    /// Query waiting to be sent by the main loop.
+    pub query: Option<API>,
+    /// The last query sent.
+    api: Option<API>,
+    /// Incremented by each new query, so that responses of older ones are
+    /// dropped.
+    pub generation: u64,
+    /// Whether a query is in flight.
+    pub loading: bool,
+    /// Error of the last query.
+    pub error: Option<String>,
+    /// Whether the next page may have more sets.
+    pub has_more: bool,
+    /// Offset of the next page.
+    next_offset: u32,
+    /// Sets waiting for their difficulties to be requested by the main loop.
+    pub pending_details: Vec<u32>,
+    pub sets: Vec<BeatmapSet>,
+    /// Selection state of `sets`.
+    pub results: MultiSelectList<'a>,
+    /// Sets waiting to be downloaded by the main loop.
+    pub pending_downloads: Vec<u32>,
+    pub downloads: Vec<DownloadItem>,
+    pub downloads_cursor: usize,
+    /// Sets in the local `Songs` directory.
+    pub library: Arc<Library>,
+    /// Sets downloaded in this session.
+    pub downloaded: HashSet<u32>,
+    /// Settings merged with the command line flags.
+    pub config: Config,
+    /// Record of downloads across runs.
+    pub download_history: Option<Arc<History>>,
 }
 
 impl<'a> Default for App<'a> {
@@ -48,6 +199,7 @@ impl<'a> Default for App<'a> {
         App {
             layer: 1,
             force_quit: false,
+            cancel: CancellationToken::new(),
             mode: SelectList::new(
                 [
                     t!("mode.date"),
@@ -72,8 +224,10 @@ impl<'a> Default for App<'a> {
             date: OffsetDateTime::now_utc().date(),
             session: 0,
             page: Page::default(),
+            history: vec![],
             text: TextArea::default(),
             text2: TextArea::default(),
+            search_error: None,
             map_state: MultiSelectList::new(
                 [
                     "Ranked & Approved",
@@ -86,6 +240,42 @@ impl<'a> Default for App<'a> {
                 .map(Line::raw),
                 DefaultSelection::Partial([0, 1, 2].into()),
             ),
+            variant: SelectList::new(
+                [
+                    t!("variant.full"),
+                    t!("variant.no_video"),
+                    t!("variant.mini"),
+                ]
+                .into_iter()
+                .map(Line::raw),
+            ),
+            filters: RangeInputs::new([
+                t!("results.stars"),
+                "BPM".to_string(),
+                t!("results.length"),
+                "AR".to_string(),
+                "OD".to_string(),
+                "CS".to_string(),
+                "HP".to_string(),
+                t!("filters.keys"),
+            ]),
+            query: None,
+            api: None,
+            generation: 0,
+            loading: false,
+            error: None,
+            has_more: false,
+            next_offset: 0,
+            pending_details: vec![],
+            sets: vec![],
+            results: MultiSelectList::default(),
+            pending_downloads: vec![],
+            downloads: vec![],
+            downloads_cursor: 0,
+            library: Default::default(),
+            downloaded: HashSet::new(),
+            config: Config::default(),
+            download_history: None,
         }
     }
 }
@@ -96,36 +286,260 @@ impl<'a> App<'a> {
         Self::default()
     }
 
+    /// Constructs a new instance of [`App`] with the defaults in `config`.
+    pub fn with_config(config: Config) -> Self {
+        let mut app = Self::default();
+        app.item = MultiSelectList::new(
+            app.item.list.clone(),
+            DefaultSelection::Partial(bit_indices(config.modes.bits())),
+        );
+        app.map_state = MultiSelectList::new(
+            app.map_state.list.clone(),
+            DefaultSelection::Partial(bit_indices(config.map_states.bits())),
+        );
+        app.variant.cursor = config.variant as usize;
+        app.config = config;
+        app
+    }
+
     /// Handles the tick event of the terminal.
     pub fn tick(&self) {}
 
     /// Go back to previous layer.
     pub fn go_back(&mut self) {
         self.layer = self.layer.saturating_sub(1);
+        if let Some(page) = self.history.pop() {
+            self.page = page;
+        }
+    }
+
+    /// Go to `page` in a new layer.
+    pub fn open(&mut self, page: Page) {
+        self.history.push(self.page);
+        self.page = page;
+        self.layer += 1;
     }
 
     /// Force to quit the program.
     pub fn force_quit(&mut self) {
         self.force_quit = true;
+        self.cancel.cancel();
     }
 
     /// Return current page.
     pub fn current_page(&self) -> Page {
-        if self.layer == 1 {
-            Page::Main
-        } else {
-            Page::Help
-        }
+        self.page
     }
 
     /// Display help page.
     pub fn help(&mut self) {
         if self.page == Page::Help {
-            self.layer -= 1;
-            self.page = Page::Main;
+            self.go_back();
         } else {
-            self.layer += 1;
-            self.page = Page::Help;
+            self.open(Page::Help);
+        }
+    }
+
+    /// Build the query from the main page. Returns `None` if the input is
+    /// invalid.
+    pub fn build_query(&mut self) -> Option<ListQuery> {
+        let list = ListMode::value_variants()[self.mode.cursor];
+        let mut query = ListQuery {
+            list: Some(list),
+            ..Default::default()
+        };
+        match list {
+            ListMode::Date => query.since = Some(self.date),
+            ListMode::Hot | ListMode::New => {
+                if !self.validate(1) {
+                    return None;
+                }
+                query.limit = self.text.lines()[0].parse().ok()?;
+            }
+            ListMode::Search => query.query = Some(self.validate_search()?),
+        }
+        query.filters = self.build_filters().ok()?;
+        query.mode = Some(GameModes::from_bits_truncate(self.item.bit_sum()));
+        query.status = Some(RankStatus::from_bits_truncate(self.map_state.bit_sum()));
+        Some(query)
+    }
+
+    /// Parse the ranges of the filter panel. Returns the index of the first
+    /// invalid one on error.
+    pub fn build_filters(&self) -> Result<Filters, usize> {
+        let mut filters = Filters::default();
+        let fields = filters.ranges().map(|(field, _)| field);
+        for (i, (field, input)) in fields.into_iter().zip(&self.filters.inputs).enumerate() {
+            let range = match (field, input.trim()) {
+                (_, "") => continue,
+                (Field::Length, input) => Range::parse_length(input),
+                (_, input) => input.parse(),
+            };
+            *filters.range_mut(field).ok_or(i)? = Some(range.map_err(|_| i)?);
+        }
+        Ok(filters)
+    }
+
+    /// The request of [`App::build_query`].
+    pub fn build_api(&mut self) -> Option<API> {
+        Some(self.build_query()?.to_api())
+    }
+
+    /// Send the query of the main page and go to the results page.
+    pub fn query(&mut self) {
+        if let Some(api) = self.build_api() {
+            self.generation += 1;
+            self.send(api);
+            self.sets.clear();
+            self.results = MultiSelectList::default();
+            self.open(Page::Results);
+        }
+    }
+
+    /// Request the next page of the last query.
+    pub fn load_more(&mut self) {
+        if self.loading || !self.has_more {
+            return;
+        }
+        if let Some(mut api) = self.api.clone() {
+            api.offset = Some(self.next_offset);
+            self.send(api);
+        }
+    }
+
+    fn send(&mut self, api: API) {
+        self.api = Some(api.clone());
+        self.query = Some(api);
+        self.loading = true;
+        self.error = None;
+    }
+
+    /// Receive the response of the query sent in `generation`. A response of
+    /// the next page is appended to the results, one of an older query is
+    /// dropped.
+    pub fn receive(&mut self, generation: u64, response: Result<BeatmapList, String>) {
+        if generation != self.generation {
+            return;
+        }
+        self.loading = false;
+        match response {
+            Ok(list) => {
+                // a filtered page may be empty with more sets after it
+                self.has_more = list.status == 0;
+                self.next_offset = list.endid;
+                self.pending_details.extend(
+                    list.data
+                        .iter()
+                        .filter(|set| set.difficulties.is_empty())
+                        .map(|set| set.sid),
+                );
+                self.results.extend(
+                    list.data
+                        .iter()
+                        .map(|set| Line::raw(set.display_title().to_string())),
+                );
+                self.sets.extend(list.data);
+            }
+            Err(e) => {
+                self.has_more = false;
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Receive the difficulties of a set.
+    pub fn receive_detail(&mut self, detail: BeatmapSet) {
+        if let Some(set) = self.sets.iter_mut().find(|set| set.sid == detail.sid) {
+            set.merge(detail);
+        }
+    }
+
+    /// Whether `sid` is in the library or downloaded in this session.
+    pub fn owns(&self, sid: u32) -> bool {
+        self.library.contains(sid) || self.downloaded.contains(&sid)
+    }
+
+    /// Sets selected on the results page.
+    pub fn selected_sets(&self) -> impl Iterator<Item = &BeatmapSet> {
+        self.results.selected().map(|i| &self.sets[i])
+    }
+
+    /// The download variant chosen on the main page.
+    pub fn download_variant(&self) -> Variant {
+        match self.variant.cursor {
+            1 => Variant::NoVideo,
+            2 => Variant::Mini,
+            _ => Variant::Full,
+        }
+    }
+
+    /// Download the selected sets, or the set under the cursor if none is
+    /// selected, and go to the downloads page.
+    pub fn download_selected(&mut self) {
+        let mut sets: Vec<&BeatmapSet> = self.selected_sets().collect();
+        if sets.is_empty() {
+            sets.extend(self.sets.get(self.results.cursor));
+        }
+        let sets: Vec<(u32, String)> = sets
+            .into_iter()
+            .map(|set| {
+                let title = format!("{} - {}", set.display_artist(), set.display_title());
+                (set.sid, title)
+            })
+            .collect();
+        for (sid, title) in sets {
+            match self.downloads.iter_mut().find(|item| item.sid == sid) {
+                Some(item) if item.state != DownloadState::Failed => continue,
+                Some(item) => *item = DownloadItem::new(sid, title),
+                None => self.downloads.push(DownloadItem::new(sid, title)),
+            }
+            self.pending_downloads.push(sid);
+        }
+        self.open(Page::Downloads);
+    }
+
+    /// Download failed sets again.
+    pub fn retry_failed(&mut self) {
+        for item in &mut self.downloads {
+            if item.state == DownloadState::Failed {
+                *item = DownloadItem::new(item.sid, std::mem::take(&mut item.title));
+                self.pending_downloads.push(item.sid);
+            }
+        }
+    }
+
+    /// Receive the progress of a download.
+    pub fn receive_download(&mut self, event: DownloadEvent) {
+        let sid = match &event {
+            DownloadEvent::Queued { sid }
+            | DownloadEvent::Progress { sid, .. }
+            | DownloadEvent::Verifying { sid }
+            | DownloadEvent::Verified { sid, .. }
+            | DownloadEvent::Done { sid, .. }
+            | DownloadEvent::Skipped { sid, .. }
+            | DownloadEvent::Failed { sid, .. } => *sid,
+        };
+        let Some(item) = self.downloads.iter_mut().find(|item| item.sid == sid) else {
+            return;
+        };
+        match event {
+            DownloadEvent::Queued { .. } => item.state = DownloadState::Queued,
+            DownloadEvent::Progress {
+                downloaded, total, ..
+            } => item.progress(downloaded, total),
+            DownloadEvent::Verifying { .. } => item.state = DownloadState::Verifying,
+            DownloadEvent::Verified { info, .. } => item.info = Some(info),
+            DownloadEvent::Done { .. } => {
+                item.state = DownloadState::Done;
+                item.speed = 0.0;
+                self.downloaded.insert(sid);
+            }
+            DownloadEvent::Skipped { .. } => item.state = DownloadState::Skipped,
+            DownloadEvent::Failed { error, .. } => {
+                item.state = DownloadState::Failed;
+                item.speed = 0.0;
+                item.error = Some(error);
+            }
         }
     }
 
@@ -161,6 +575,21 @@ impl<'a> App<'a> {
             true
         }
     }
+
+    /// Parse the search in self.text2, keeping the error for its title.
+    pub fn validate_search(&mut self) -> Option<SearchQuery> {
+        match self.text2.lines().join(" ").parse::<SearchQuery>() {
+            Ok(search) => {
+                self.search_error = None;
+                Some(search)
+            }
+            Err(e) => {
+                self.search_error = Some(e.to_string());
+                None
+            }
+        }
+    }
+
     // default session color is green
     pub fn session_color(&self, session: usize) -> Color {
         if self.session == session {
@@ -170,3 +599,131 @@ impl<'a> App<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{core::api::ListKind, download::ErrorKind};
+
+    #[test]
+    fn test_build_api() {
+        let mut app = App::new();
+        let api = app.build_api().unwrap();
+        assert_eq!(api.type_, Some(ListKind::New));
+        assert_eq!(api.since, Some(app.date));
+        assert_eq!(api.mode, Some(GameModes::all()));
+        assert_eq!(
+            api.class,
+            Some(RankStatus::RANKED | RankStatus::QUALIFIED | RankStatus::LOVED)
+        );
+
+        app.mode.next();
+        assert!(app.build_api().is_none());
+        app.text.insert_str("20");
+        let api = app.build_api().unwrap();
+        assert_eq!((api.type_, api.limit), (Some(ListKind::Hot), 20));
+
+        app.filters.inputs[0] = "5..".to_string();
+        app.filters.inputs[2] = "..2:00".to_string();
+        let search = app.build_api().unwrap().search.unwrap();
+        assert_eq!(search.to_string(), "stars>=5 length<=120");
+        app.filters.inputs[7] = "seven".to_string();
+        assert_eq!(app.build_filters(), Err(7));
+        assert!(app.build_api().is_none());
+        app.filters.inputs[7].clear();
+
+        // search mode
+        app.mode.next();
+        app.mode.next();
+        app.text2.insert_str("stars>=x");
+        assert!(app.build_api().is_none());
+        assert!(app.search_error.is_some());
+        app.text2 = TextArea::from(["stars>=6"]);
+        assert!(app.build_api().is_some());
+        assert!(app.search_error.is_none());
+    }
+
+    #[test]
+    fn test_paging() {
+        let list: BeatmapList =
+            serde_json::from_str(include_str!("../test/fixtures/beatmaplist.json")).unwrap();
+        let mut app = App::new();
+        app.query();
+        app.receive(app.generation, Ok(list.clone()));
+        assert_eq!((app.sets.len(), app.results.len()), (3, 3));
+        assert_eq!(app.pending_details.len(), 3);
+        assert!(app.has_more && !app.loading);
+
+        app.query = None;
+        app.load_more();
+        assert_eq!(app.query.as_ref().unwrap().offset, Some(3));
+        app.receive(app.generation, Ok(list.clone()));
+        assert_eq!(app.sets.len(), 6);
+
+        app.results.select(4).unwrap();
+        assert_eq!(
+            app.selected_sets().map(|set| set.sid).collect::<Vec<_>>(),
+            [2069820]
+        );
+
+        // a new query drops the page still in flight
+        app.load_more();
+        let stale = app.generation;
+        app.query();
+        app.receive(stale, Ok(list.clone()));
+        assert!(app.sets.is_empty() && app.loading);
+        app.receive(app.generation, Ok(list));
+        assert_eq!(app.sets.len(), 3);
+    }
+
+    #[test]
+    fn test_downloads() {
+        let list: BeatmapList =
+            serde_json::from_str(include_str!("../test/fixtures/beatmaplist.json")).unwrap();
+        let mut app = App::new();
+        app.query();
+        app.receive(app.generation, Ok(list));
+        app.results.select(0).unwrap();
+        app.results.select(2).unwrap();
+        app.download_selected();
+        assert_eq!(app.current_page(), Page::Downloads);
+        assert_eq!(app.pending_downloads, [2071043, 2070511]);
+
+        app.receive_download(DownloadEvent::Progress {
+            sid: 2071043,
+            downloaded: 50,
+            total: Some(100),
+        });
+        assert_eq!(app.downloads[0].state, DownloadState::Downloading);
+        assert_eq!(app.downloads[0].ratio(), 0.5);
+        app.receive_download(DownloadEvent::Failed {
+            sid: 2070511,
+            kind: ErrorKind::NotFound,
+            error: "404".to_string(),
+        });
+        assert_eq!(app.downloads[1].state, DownloadState::Failed);
+        app.receive_download(DownloadEvent::Done {
+            sid: 2071043,
+            path: "2071043.osz".into(),
+        });
+        assert!(app.owns(2071043));
+
+        app.pending_downloads.clear();
+        app.retry_failed();
+        assert_eq!(app.pending_downloads, [2070511]);
+        assert_eq!(app.downloads[1].state, DownloadState::Queued);
+    }
+
+    #[test]
+    fn test_pages() {
+        let mut app = App::new();
+        app.query();
+        assert_eq!((app.current_page(), app.layer), (Page::Results, 2));
+        assert!(app.loading && app.query.is_some());
+        app.help();
+        app.help();
+        assert_eq!(app.current_page(), Page::Results);
+        app.go_back();
+        assert_eq!((app.current_page(), app.layer), (Page::Main, 1));
+    }
+}
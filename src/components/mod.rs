@@ -1,2 +1,3 @@
 pub mod multi_select_list;
+pub mod range_inputs;
 pub mod select_list;
@@ -55,7 +55,7 @@ impl<'a> MultiSelectList<'a> {
     }
 
     /// Select the given index. If index has been selected, it will cancel the selection.
-    pub fn select(&mut self, index: usize) -> Result<&Line> {
+    pub fn select(&mut self, index: usize) -> Result<&Line<'a>> {
         let line = self.list.get_mut(index).expect("select index out of range");
         if self.selected.insert(index) {
             line.patch_style(self.selected_color);
@@ -66,8 +66,38 @@ impl<'a> MultiSelectList<'a> {
         Ok(line)
     }
 
+    /// Append items, unselected.
+    pub fn extend<T>(&mut self, list: T)
+    where
+        T: IntoIterator,
+        T::Item: Into<Line<'a>>,
+    {
+        self.list.extend(list.into_iter().map(|i| {
+            let mut line: Line = i.into();
+            line.patch_style(Style::default().white());
+            line
+        }));
+    }
+
+    pub fn is_selected(&self, index: usize) -> bool {
+        self.selected.contains(&index)
+    }
+
+    /// Indices of selected items, in ascending order.
+    pub fn selected(&self) -> impl Iterator<Item = usize> + '_ {
+        self.selected.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
     /// Select the cursor index.
-    pub fn select_cursor(&mut self) -> &Line {
+    pub fn select_cursor(&mut self) -> &Line<'a> {
         self.select(self.cursor).unwrap()
     }
 
@@ -91,7 +121,7 @@ impl<'a> MultiSelectList<'a> {
         self.cursor
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &Line> {
+    pub fn iter(&self) -> impl Iterator<Item = &Line<'a>> {
         self.list.iter()
     }
 
@@ -133,5 +163,10 @@ mod tests {
         list.select(2).unwrap();
         assert_eq!(list.selected, BTreeSet::from([0, 2]));
         assert_eq!(list.bit_sum(), 0b101);
+        list.extend(["Eve"]);
+        assert_eq!(list.len(), 5);
+        assert!(!list.is_selected(4));
+        list.select(4).unwrap();
+        assert_eq!(list.selected().collect::<Vec<_>>(), [0, 2, 4]);
     }
 }
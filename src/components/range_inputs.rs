@@ -0,0 +1,107 @@
+use crossterm::event::KeyCode;
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, HighlightSpacing, List, ListState},
+    Frame,
+};
+
+/// Labelled one-line text inputs, edited in place at the cursor.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct RangeInputs<'a> {
+    pub labels: Vec<Span<'a>>,
+    pub inputs: Vec<String>,
+    pub cursor: usize,
+}
+
+impl<'a> RangeInputs<'a> {
+    pub fn new<T>(labels: T) -> Self
+    where
+        T: IntoIterator,
+        T::Item: Into<Span<'a>>,
+    {
+        let labels: Vec<_> = labels.into_iter().map(|i| i.into()).collect();
+        Self {
+            inputs: vec![String::new(); labels.len()],
+            labels,
+            cursor: 0,
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.cursor = (self.cursor + 1).rem_euclid(self.labels.len());
+    }
+
+    pub fn prev(&mut self) {
+        self.cursor = (self.cursor as isize - 1).rem_euclid(self.labels.len() as isize) as usize;
+    }
+
+    /// Edit the input at the cursor. Returns whether the key is handled.
+    pub fn input(&mut self, key: KeyCode) -> bool {
+        let input = &mut self.inputs[self.cursor];
+        match key {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Delete => input.clear(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// render the inputs, the one at `invalid` in red.
+    pub fn render(&mut self, frame: &mut Frame, block: Block, area: Rect, invalid: Option<usize>) {
+        let width = self.labels.iter().map(Span::width).max().unwrap_or(0);
+        let lines: Vec<_> = self
+            .labels
+            .iter()
+            .zip(&self.inputs)
+            .enumerate()
+            .map(|(i, (label, input))| {
+                let color = if invalid == Some(i) {
+                    Color::LightRed
+                } else {
+                    Color::White
+                };
+                Line::from(vec![
+                    label.clone(),
+                    Span::raw(" ".repeat(width - label.width() + 1)),
+                    Span::styled(input.clone(), Style::default().fg(color)),
+                ])
+            })
+            .collect();
+        let styled_list = List::new(lines)
+            .style(Style::default().fg(Color::White))
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_symbol("> ")
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+            .block(block);
+        frame.render_stateful_widget(
+            styled_list,
+            area,
+            &mut ListState::default().with_selected(Some(self.cursor)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input() {
+        let mut inputs = RangeInputs::new(["a", "b"]);
+        inputs.next();
+        for c in "5..7".chars() {
+            assert!(inputs.input(KeyCode::Char(c)));
+        }
+        inputs.input(KeyCode::Backspace);
+        assert!(!inputs.input(KeyCode::Enter));
+        assert_eq!(inputs.inputs, ["", "5.."]);
+        inputs.prev();
+        inputs.prev();
+        assert_eq!(inputs.cursor, 1);
+    }
+}
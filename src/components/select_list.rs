@@ -34,7 +34,7 @@ impl<'a> SelectList<'a> {
         assert!(self.cursor < self.list.len());
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &Line> {
+    pub fn iter(&self) -> impl Iterator<Item = &Line<'a>> {
         self.list.iter()
     }
 
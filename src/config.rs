@@ -0,0 +1,481 @@
+//! Per-user defaults, read from a TOML file and overridden by command line
+//! flags.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use crossterm::event::KeyCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        api::{names, Cli, DownloadOptions, GameModes, RankStatus},
+        mirror::{Mirrors, Variant},
+        query::ListQuery,
+    },
+    download::extract::Extract,
+};
+
+/// Settings of the app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Where downloaded `.osz` files are saved.
+    pub output: PathBuf,
+    /// The `Songs` directory of osu!.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub songs: Option<PathBuf>,
+    /// Extract downloaded sets into `songs`.
+    pub extract: bool,
+    /// Delete the `.osz` after extracting.
+    pub delete_osz: bool,
+    /// Builtin mirror names or sayobot compatible urls, by priority. Empty
+    /// for all builtin mirrors.
+    pub mirrors: Vec<String>,
+    /// Downloads at the same time.
+    pub jobs: usize,
+    /// Downloads at the same time from one mirror.
+    pub per_host: usize,
+    /// Game modes selected by default.
+    #[serde(with = "names")]
+    pub modes: GameModes,
+    /// Map states selected by default.
+    #[serde(with = "names")]
+    pub map_states: RankStatus,
+    pub variant: Variant,
+    /// Language of the interface, the system one if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Milliseconds between ticks of the interface.
+    pub tick_rate: u64,
+    /// Keep the download history.
+    pub history: bool,
+    pub keys: Keys,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            output: PathBuf::from("."),
+            songs: None,
+            extract: false,
+            delete_osz: false,
+            mirrors: vec![],
+            jobs: 4,
+            per_host: 2,
+            modes: GameModes::all(),
+            map_states: RankStatus::RANKED | RankStatus::QUALIFIED | RankStatus::LOVED,
+            variant: Variant::Full,
+            locale: None,
+            tick_rate: 250,
+            history: true,
+            keys: Keys::default(),
+        }
+    }
+}
+
+impl Config {
+    /// `config.toml` in the user config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join("beatmap-downloader")
+                .join("config.toml"),
+        )
+    }
+
+    /// Read the config at `path`. A missing file gives the defaults. It is
+    /// validated only after the command line flags are merged, which may fix
+    /// it.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        toml::from_str(&content).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    /// Write the defaults to `path`, unless it exists and `force` is not set.
+    pub fn init(path: &Path, force: bool) -> Result<()> {
+        if path.exists() && !force {
+            bail!("{} already exists", path.display());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, Self::default().to_toml()?)?;
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Check values that deserialize fine but are not usable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.jobs > 0, "jobs must be positive");
+        ensure!(self.per_host > 0, "per_host must be positive");
+        ensure!(self.tick_rate > 0, "tick_rate must be positive");
+        ensure!(
+            !self.delete_osz || self.extract,
+            "delete_osz needs extract to be set"
+        );
+        ensure!(
+            !self.extract || self.songs.is_some(),
+            "extract needs songs to be set"
+        );
+        Mirrors::from_names(&self.mirrors)?;
+        self.keys.validate()
+    }
+
+    /// Override with the global flags given on the command line.
+    pub fn merge(&mut self, cli: &Cli) {
+        if let Some(url) = &cli.url {
+            self.mirrors = vec![url.clone()];
+        }
+        if let Some(songs) = &cli.songs {
+            self.songs = Some(songs.clone());
+        }
+        self.extract = (self.extract || cli.extract) && !cli.no_extract;
+        self.delete_osz =
+            (self.delete_osz || cli.delete_osz) && !cli.no_extract && !cli.no_delete_osz;
+        self.history &= !cli.no_history;
+    }
+
+    /// Override with the flags of the commands downloading sets.
+    pub fn merge_download(&mut self, args: &DownloadOptions) {
+        if let Some(output) = &args.output {
+            self.output = output.clone();
+        }
+        self.jobs = args.jobs.unwrap_or(self.jobs);
+        self.per_host = args.per_host.unwrap_or(self.per_host);
+        self.variant = args.variant.unwrap_or(self.variant);
+    }
+
+    /// Fill the game modes and map states not given in `query`.
+    pub fn fill_query(&self, query: &mut ListQuery) {
+        query.mode.get_or_insert(self.modes);
+        query.status.get_or_insert(self.map_states);
+    }
+
+    /// The mirrors to use. Validated on load.
+    pub fn mirrors(&self) -> Result<Mirrors> {
+        Mirrors::from_names(&self.mirrors)
+    }
+
+    /// How to extract downloaded sets, if asked.
+    pub fn extract(&self) -> Option<Extract> {
+        let songs = self.songs.as_ref().filter(|_| self.extract)?;
+        Some(Extract::new(songs).delete_osz(self.delete_osz))
+    }
+}
+
+/// Keys of actions in the interface.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    /// Go back, or quit on the main page.
+    pub back: Key,
+    /// Send the query, or download the selected sets.
+    pub confirm: Key,
+    /// Select the item under the cursor.
+    pub select: Key,
+    /// Retry failed downloads.
+    pub retry: Key,
+    /// Load the next page of results.
+    pub load_more: Key,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self {
+            back: Key(KeyCode::Esc),
+            confirm: Key(KeyCode::Enter),
+            select: Key(KeyCode::Char(' ')),
+            retry: Key(KeyCode::Char('r')),
+            load_more: Key(KeyCode::PageDown),
+        }
+    }
+}
+
+/// Keys moving the cursor everywhere, which cannot be bound.
+const CURSOR_KEYS: [KeyCode; 4] = [KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right];
+
+/// Keys editing text, besides characters.
+const EDITING_KEYS: [KeyCode; 4] = [
+    KeyCode::Backspace,
+    KeyCode::Delete,
+    KeyCode::Home,
+    KeyCode::End,
+];
+
+impl Keys {
+    fn validate(&self) -> Result<()> {
+        let keys = [
+            &self.back,
+            &self.confirm,
+            &self.select,
+            &self.retry,
+            &self.load_more,
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            if CURSOR_KEYS.contains(&key.0) {
+                bail!("key {key} moves the cursor and cannot be bound");
+            }
+            if keys[..i].iter().any(|other| other.matches(key.0)) {
+                bail!("key {key} is bound twice");
+            }
+        }
+        // back and confirm also work while typing in text inputs
+        for key in [&self.back, &self.confirm] {
+            if matches!(key.0, KeyCode::Char(_)) || EDITING_KEYS.contains(&key.0) {
+                bail!("key {key} is typed into text inputs and cannot be bound to back or confirm");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A key, written like `Enter`, `Space`, `F5` or `r`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Key(pub KeyCode);
+
+impl Key {
+    /// Whether `code` is this key. Letters match in both cases.
+    pub fn matches(&self, code: KeyCode) -> bool {
+        match (self.0, code) {
+            (KeyCode::Char(a), KeyCode::Char(b)) => a.eq_ignore_ascii_case(&b),
+            (a, b) => a == b,
+        }
+    }
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 15] = [
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Space", KeyCode::Char(' ')),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+];
+
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((_, code)) = NAMED_KEYS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(Self(*code));
+        }
+        if let Some(n) = s.strip_prefix(['F', 'f']).and_then(|n| n.parse().ok()) {
+            if (1..=12).contains(&n) {
+                return Ok(Self(KeyCode::F(n)));
+            }
+        }
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if !c.is_control() => Ok(Self(KeyCode::Char(c))),
+            _ => bail!("unknown key `{s}`"),
+        }
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, code)| *code == self.0) {
+            return f.write_str(name);
+        }
+        match self.0 {
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> Self {
+        key.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::core::api::Commands;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+
+        Config::init(&path, false).unwrap();
+        assert!(Config::init(&path, false).is_err());
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+
+        fs::write(
+            &path,
+            r#"
+            output = "osz"
+            mirrors = ["nerinyan", "https://example.com"]
+            modes = ["mania"]
+            variant = "no-video"
+
+            [keys]
+            retry = "F5"
+            "#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.output, Path::new("osz"));
+        assert_eq!(config.modes, GameModes::MANIA);
+        assert_eq!(config.map_states, Config::default().map_states);
+        assert_eq!(config.variant, Variant::NoVideo);
+        assert_eq!(config.keys.retry, Key(KeyCode::F(5)));
+        assert_eq!(config.keys.back, Key(KeyCode::Esc));
+        assert_eq!(config.mirrors().unwrap().len(), 2);
+
+        for invalid in [
+            "modes = [\"osu\"]",
+            "unknown = 1",
+            "[keys]\nretry = \"Ctrl\"",
+        ] {
+            fs::write(&path, invalid).unwrap();
+            assert!(Config::load(&path).is_err(), "{invalid}");
+        }
+        for invalid in [
+            "jobs = 0",
+            "mirrors = [\"example.com\"]",
+            "delete_osz = true",
+            "[keys]\nselect = \"Enter\"",
+        ] {
+            fs::write(&path, invalid).unwrap();
+            assert!(
+                Config::load(&path).unwrap().validate().is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_fixed_by_cli() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let load = |content: &str, args: &[&str]| {
+            fs::write(&path, content).unwrap();
+            let mut config = Config::load(&path).unwrap();
+            config.merge(&Cli::parse_from(["beatmap-downloader"].iter().chain(args)));
+            config.validate()
+        };
+        assert!(load("extract = true", &[]).is_err());
+        assert!(load("extract = true", &["--songs", "Songs"]).is_ok());
+        assert!(load("delete_osz = true", &[]).is_err());
+        assert!(load("delete_osz = true", &["--no-delete-osz"]).is_ok());
+        assert!(load("delete_osz = true", &["--extract", "--songs", "Songs"]).is_ok());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut config = Config {
+            jobs: 8,
+            mirrors: vec!["catboy".to_string()],
+            ..Default::default()
+        };
+        let cli = Cli::parse_from([
+            "beatmap-downloader",
+            "--url",
+            "http://localhost:5000",
+            "-s",
+            "Songs",
+            "--extract",
+            "--no-history",
+            "download",
+            "--limit",
+            "1",
+            "--per-host",
+            "1",
+        ]);
+        config.merge(&cli);
+        let Some(Commands::Download(args)) = &cli.download else {
+            panic!("not a download command");
+        };
+        config.merge_download(&args.options);
+        let mut query = args.query.clone();
+        config.fill_query(&mut query);
+        assert_eq!(query.mode, Some(GameModes::all()));
+        assert_eq!(config.mirrors, ["http://localhost:5000"]);
+        assert_eq!(config.extract().unwrap(), Extract::new("Songs"));
+        assert!(!config.history);
+        assert_eq!((config.jobs, config.per_host), (8, 1));
+        assert_eq!(config.output, Path::new("."));
+
+        // the config file is turned off from the command line
+        let mut config = Config {
+            songs: Some(PathBuf::from("Songs")),
+            extract: true,
+            delete_osz: true,
+            ..Default::default()
+        };
+        config.merge(&Cli::parse_from(["beatmap-downloader", "--no-delete-osz"]));
+        assert!(config.extract && !config.delete_osz);
+        config.delete_osz = true;
+        config.merge(&Cli::parse_from(["beatmap-downloader", "--no-extract"]));
+        assert!(!config.extract && !config.delete_osz);
+        assert!(config.validate().is_ok());
+        assert!(Cli::try_parse_from(["beatmap-downloader", "--extract", "--no-extract"]).is_err());
+    }
+
+    #[test]
+    fn test_key() {
+        for s in ["Enter", "Space", "PageDown", "Right", "F12", "r", "?"] {
+            assert_eq!(s.parse::<Key>().unwrap().to_string(), s);
+        }
+        assert_eq!("esc".parse::<Key>().unwrap(), Key(KeyCode::Esc));
+        assert!("F13".parse::<Key>().is_err());
+        assert!("rr".parse::<Key>().is_err());
+        assert!(Key(KeyCode::Char('r')).matches(KeyCode::Char('R')));
+    }
+
+    #[test]
+    fn test_keys() {
+        assert!(Keys::default().validate().is_ok());
+        let keys = |f: fn(&mut Keys)| {
+            let mut keys = Keys::default();
+            f(&mut keys);
+            keys.validate()
+        };
+        assert!(keys(|keys| keys.retry = Key(KeyCode::Char(' '))).is_err());
+        assert!(keys(|keys| keys.load_more = Key(KeyCode::Down)).is_err());
+        assert!(keys(|keys| keys.back = Key(KeyCode::Char('q'))).is_err());
+        assert!(keys(|keys| keys.confirm = Key(KeyCode::Backspace)).is_err());
+        assert!(keys(|keys| keys.back = Key(KeyCode::F(10))).is_ok());
+    }
+}
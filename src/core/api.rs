@@ -1,44 +1,424 @@
+use std::path::PathBuf;
+
+use bitflags::bitflags;
 use clap::{Args, Parser, Subcommand};
-use serde::Serialize;
+use serde::{Serialize, Serializer};
+use time::{format_description::well_known::Iso8601, Date};
+
+use super::{
+    beatmap::BeatmapSet,
+    mirror::Variant,
+    pack::{PackKind, PackRange},
+    query::ListQuery,
+    search::SearchQuery,
+};
+use crate::{
+    download::history::{HistoryEntry, Outcome},
+    output::Format,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    #[arg(short, long)]
-    url: Option<String>,
-    #[arg(short, long)]
-    debug: Option<bool>,
+    #[arg(short, long, help = t!("cli.url"))]
+    pub url: Option<String>,
+    #[arg(short, long, help = t!("cli.debug"))]
+    pub debug: bool,
+    #[arg(long, default_value = "beatmap-downloader.log", help = t!("cli.log_file"))]
+    pub log_file: PathBuf,
+    #[arg(short, long, help = t!("cli.songs"))]
+    pub songs: Option<PathBuf>,
+    #[arg(long, help = t!("cli.extract"))]
+    pub extract: bool,
+    #[arg(long, conflicts_with_all = ["extract", "delete_osz"], help = t!("cli.no_extract"))]
+    pub no_extract: bool,
+    #[arg(long, help = t!("cli.delete_osz"))]
+    pub delete_osz: bool,
+    #[arg(long, conflicts_with = "delete_osz", help = t!("cli.no_delete_osz"))]
+    pub no_delete_osz: bool,
+    #[arg(long, help = t!("cli.no_history"))]
+    pub no_history: bool,
+    #[arg(short, long, help = t!("cli.config"))]
+    pub config: Option<PathBuf>,
     #[command(subcommand)]
     pub download: Option<Commands>,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
-    Download(API),
+    /// Print the beatmap list.
+    List(ListArgs),
+    /// Download all beatmap sets in the beatmap list.
+    Download(DownloadArgs),
+    /// List or download official beatmap packs, served by the catboy mirror.
+    Packs {
+        #[command(subcommand)]
+        action: PacksAction,
+    },
+    /// Print or export the download history.
+    History(HistoryArgs),
+    /// Print, validate or create the config file.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, Copy)]
+pub enum ConfigAction {
+    /// Print the config merged with the command line flags.
+    Print,
+    /// Check the config file with the other flags given, as a run would.
+    Validate,
+    /// Write the default config file.
+    Init {
+        #[arg(long, help = t!("cli.force"))]
+        force: bool,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub query: ListQuery,
+    #[arg(long, value_enum, default_value_t = Format::Table, help = t!("cli.format"))]
+    pub format: Format,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DownloadArgs {
+    #[command(flatten)]
+    pub query: ListQuery,
+    #[command(flatten)]
+    pub options: DownloadOptions,
+}
+
+/// Flags shared by the commands downloading sets.
+#[derive(Args, Debug, Clone)]
+pub struct DownloadOptions {
+    #[arg(long, value_enum, default_value_t = Format::Table, help = t!("cli.format"))]
+    pub format: Format,
+    #[arg(short, long, help = t!("cli.output"))]
+    pub output: Option<PathBuf>,
+    #[arg(short, long, help = t!("cli.jobs"))]
+    pub jobs: Option<usize>,
+    #[arg(long, help = t!("cli.per_host"))]
+    pub per_host: Option<usize>,
+    #[arg(long, value_enum, help = t!("cli.variant"))]
+    pub variant: Option<Variant>,
+    /// `collection.db` of osu! to add the downloaded beatmaps to.
+    #[arg(long, help = t!("cli.collection"))]
+    pub collection: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PacksAction {
+    /// Print the official beatmap packs of a kind.
+    List {
+        #[arg(value_enum, default_value_t, help = t!("cli.pack_kind"))]
+        kind: PackKind,
+        #[arg(long, value_enum, default_value_t = Format::Table, help = t!("cli.format"))]
+        format: Format,
+    },
+    /// Download every beatmap set in packs.
+    Download(PackDownloadArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PackDownloadArgs {
+    /// Like `S1000-S1100`, `L12` or both separated by commas.
+    #[arg(required = true, value_delimiter = ',', help = t!("cli.packs"))]
+    pub packs: Vec<PackRange>,
+    #[command(flatten)]
+    pub options: DownloadOptions,
+}
+
+impl Commands {
+    /// The download flags of commands downloading sets.
+    pub fn download_options(&self) -> Option<&DownloadOptions> {
+        match self {
+            Self::Download(args) => Some(&args.options),
+            Self::Packs {
+                action: PacksAction::Download(args),
+            } => Some(&args.options),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct HistoryArgs {
+    #[arg(long, help = t!("cli.history_sid"))]
+    pub sid: Option<u32>,
+    #[arg(long, value_enum, help = t!("cli.history_outcome"))]
+    pub outcome: Option<Outcome>,
+    #[arg(long, help = t!("cli.history_mirror"))]
+    pub mirror: Option<String>,
+    #[arg(long, value_parser = parse_date, help = t!("cli.history_since"))]
+    pub since: Option<Date>,
+    /// Write the matching entries as JSON lines instead of printing them.
+    #[arg(long, help = t!("cli.history_export"))]
+    pub export: Option<PathBuf>,
+}
+
+impl HistoryArgs {
+    /// Whether `entry` passes all the filters.
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.sid.is_none_or(|sid| entry.sid == sid)
+            && self.outcome.is_none_or(|outcome| entry.outcome == outcome)
+            && self
+                .mirror
+                .as_ref()
+                .is_none_or(|mirror| entry.mirror.as_ref() == Some(mirror))
+            && self.since.is_none_or(|since| {
+                entry.timestamp >= since.midnight().assume_utc().unix_timestamp()
+            })
+    }
+}
+
+/// Which list to request, serialized as the sayobot `type`. Users choose a
+/// [`ListMode`](super::query::ListMode) instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListKind {
+    Hot = 1,
+    New = 2,
+    Packs = 3,
+    Search = 4,
+}
+
+impl Serialize for ListKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+bitflags! {
+    /// Game modes, serialized as the sayobot `mode` bit sum.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct GameModes: u32 {
+        const STD = 1;
+        const TAIKO = 1 << 1;
+        const CTB = 1 << 2;
+        const MANIA = 1 << 3;
+    }
+
+    /// Map states, serialized as the sayobot `class` bit sum.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct RankStatus: u32 {
+        /// Ranked and approved.
+        const RANKED = 1;
+        const QUALIFIED = 1 << 1;
+        const LOVED = 1 << 2;
+        /// Pending and WIP.
+        const PENDING = 1 << 3;
+        const GRAVEYARD = 1 << 4;
+    }
+}
+
+impl Serialize for GameModes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.bits())
+    }
+}
+
+impl Serialize for RankStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.bits())
+    }
 }
 
-#[derive(Args, Debug, Serialize, Clone)]
+/// Flags written as comma separated lowercase names like `std,mania`, on the
+/// command line and in the config.
+pub mod names {
+    use bitflags::Flags;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    /// Lowercase names of the flags set in `flags`.
+    pub fn names<F: Flags>(flags: &F) -> Vec<String> {
+        flags
+            .iter_names()
+            .map(|(name, _)| name.to_lowercase())
+            .collect()
+    }
+
+    /// Parse names separated by commas, or `all`.
+    pub fn parse<F: Flags>(s: &str) -> Result<F, String> {
+        if s.trim() == "all" {
+            return Ok(F::all());
+        }
+        s.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .try_fold(F::empty(), |flags, name| {
+                let flag = F::from_name(&name.to_uppercase()).ok_or_else(|| {
+                    let names: Vec<_> = F::FLAGS.iter().map(|f| f.name().to_lowercase()).collect();
+                    format!("unknown `{name}`, expected {}", names.join(", "))
+                })?;
+                Ok(flags.union(flag))
+            })
+    }
+
+    pub fn serialize<F: Flags, S: Serializer>(flags: &F, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(names(flags))
+    }
+
+    pub fn deserialize<'de, F: Flags, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<F, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        parse(&names.join(",")).map_err(D::Error::custom)
+    }
+}
+
+/// A `beatmaplist` request in the wire format of sayobot.
+#[derive(Debug, Serialize, Clone, PartialEq)]
 pub struct API {
     /// Sayobot needs a post value: "cmd": "beatmaplist"
-    #[clap(skip)]
     cmd: String,
-    #[arg(long, help = t!("cli.limit"))]
     pub limit: u32,
-    #[arg(long, help = t!("cli.offset"))]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub offset: Option<u32>,
-    #[arg(long, help = t!("cli.type"))]
     #[serde(rename = "type")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub type_: Option<u32>,
+    pub type_: Option<ListKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<GameModes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<RankStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyword: Option<String>,
+    /// Filters of a search, also checked on the returned sets.
+    #[serde(skip)]
+    pub search: Option<SearchQuery>,
+    /// Page through the newest sets until this date, instead of one page.
+    #[serde(skip)]
+    pub since: Option<Date>,
+}
+
+/// Parse a date like `2024-01-31`.
+pub fn parse_date(s: &str) -> Result<Date, time::error::Parse> {
+    Date::parse(s, &Iso8601::DATE)
 }
 
 impl API {
-    pub fn with_cmd(mut self, cmd: String) -> Self {
-        self.cmd = cmd;
-        self
+    /// Constructs a `beatmaplist` request of `limit` sets.
+    pub fn new(limit: u32) -> Self {
+        Self {
+            cmd: "beatmaplist".to_string(),
+            limit,
+            offset: None,
+            type_: None,
+            mode: None,
+            class: None,
+            keyword: None,
+            search: None,
+            since: None,
+        }
+    }
+
+    /// Whether `set` matches the game modes and map states of the query.
+    pub fn matches(&self, set: &BeatmapSet) -> bool {
+        self.mode
+            .is_none_or(|mode| mode.intersects(GameModes::from_bits_truncate(set.modes.into())))
+            && self
+                .class
+                .is_none_or(|class| class.intersects(set.approved.rank_status()))
+    }
+
+    /// A short name of the query, like `Hot 2026-10-18 mania`.
+    pub fn name(&self, today: Date) -> String {
+        let mut name = match (self.since, self.type_) {
+            (Some(since), _) => format!("Since {since}"),
+            (None, Some(ListKind::Hot)) => format!("Hot {today}"),
+            (None, Some(ListKind::Packs)) => format!("Packs {today}"),
+            (None, Some(ListKind::Search)) => match &self.search {
+                Some(search) => format!("Search \"{search}\" {today}"),
+                None => format!(
+                    "Search \"{}\" {today}",
+                    self.keyword.as_deref().unwrap_or_default()
+                ),
+            },
+            (None, _) => format!("New {today}"),
+        };
+        if let Some(mode) = self.mode.filter(|mode| !mode.is_all()) {
+            name += " ";
+            name += &names::names(&mode).join(",");
+        }
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_matches() {
+        let mut entry = HistoryEntry::done(1, Variant::Full, "1.osz".into());
+        entry.mirror = Some("sayobot".to_string());
+        entry.timestamp = parse_date("2024-01-31")
+            .unwrap()
+            .midnight()
+            .assume_utc()
+            .unix_timestamp();
+        let mut args = HistoryArgs {
+            sid: Some(1),
+            outcome: Some(Outcome::Done),
+            mirror: Some("sayobot".to_string()),
+            since: Some(parse_date("2024-01-31").unwrap()),
+            export: None,
+        };
+        assert!(args.matches(&entry));
+        args.since = Some(parse_date("2024-02-01").unwrap());
+        assert!(!args.matches(&entry));
+        args.since = None;
+        args.outcome = Some(Outcome::Failed);
+        assert!(!args.matches(&entry));
+        args.outcome = None;
+        args.mirror = Some("catboy".to_string());
+        assert!(!args.matches(&entry));
+    }
+
+    #[test]
+    fn test_name() {
+        let today = parse_date("2026-10-18").unwrap();
+        let mut api = API::new(50);
+        api.type_ = Some(ListKind::Hot);
+        api.mode = Some(GameModes::MANIA);
+        assert_eq!(api.name(today), "Hot 2026-10-18 mania");
+        api.mode = Some(GameModes::all());
+        assert_eq!(api.name(today), "Hot 2026-10-18");
+        api.type_ = Some(ListKind::Search);
+        api.keyword = Some("camellia".to_string());
+        api.mode = Some(GameModes::STD | GameModes::TAIKO);
+        assert_eq!(api.name(today), "Search \"camellia\" 2026-10-18 std,taiko");
+        api.since = parse_date("2024-01-31").ok();
+        assert_eq!(api.name(today), "Since 2024-01-31 std,taiko");
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let mut api = API::new(50);
+        api.type_ = Some(ListKind::Search);
+        api.mode = Some(GameModes::STD | GameModes::MANIA);
+        api.class = Some(RankStatus::RANKED | RankStatus::LOVED);
+        assert_eq!(
+            serde_json::to_string(&api).unwrap(),
+            r#"{"cmd":"beatmaplist","limit":50,"type":4,"mode":9,"class":5}"#
+        );
     }
-    pub fn set_cmd(&mut self, cmd: String) {
-        self.cmd = cmd;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            names::parse::<GameModes>("std, mania"),
+            Ok(GameModes::STD | GameModes::MANIA)
+        );
+        assert_eq!(names::parse::<RankStatus>("all"), Ok(RankStatus::all()));
+        assert!(names::parse::<GameModes>("osu").is_err());
+        assert_eq!(
+            names::names(&(RankStatus::QUALIFIED | RankStatus::GRAVEYARD)),
+            ["qualified", "graveyard"]
+        );
     }
 }
@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+
+use super::api::{GameModes, RankStatus};
+
+/// Ranked status of a beatmap set, as the `approved` field of osu! API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "i8", into = "i8")]
+pub enum RankedStatus {
+    Graveyard,
+    Wip,
+    Pending,
+    Ranked,
+    Approved,
+    Qualified,
+    Loved,
+    Unknown(i8),
+}
+
+impl From<i8> for RankedStatus {
+    fn from(value: i8) -> Self {
+        match value {
+            -2 => Self::Graveyard,
+            -1 => Self::Wip,
+            0 => Self::Pending,
+            1 => Self::Ranked,
+            2 => Self::Approved,
+            3 => Self::Qualified,
+            4 => Self::Loved,
+            x => Self::Unknown(x),
+        }
+    }
+}
+
+impl From<RankedStatus> for i8 {
+    fn from(value: RankedStatus) -> Self {
+        match value {
+            RankedStatus::Graveyard => -2,
+            RankedStatus::Wip => -1,
+            RankedStatus::Pending => 0,
+            RankedStatus::Ranked => 1,
+            RankedStatus::Approved => 2,
+            RankedStatus::Qualified => 3,
+            RankedStatus::Loved => 4,
+            RankedStatus::Unknown(x) => x,
+        }
+    }
+}
+
+impl RankedStatus {
+    /// The flag of the status in map states of a query.
+    pub fn rank_status(self) -> RankStatus {
+        match self {
+            Self::Ranked | Self::Approved => RankStatus::RANKED,
+            Self::Qualified => RankStatus::QUALIFIED,
+            Self::Loved => RankStatus::LOVED,
+            Self::Pending | Self::Wip => RankStatus::PENDING,
+            Self::Graveyard => RankStatus::GRAVEYARD,
+            Self::Unknown(_) => RankStatus::empty(),
+        }
+    }
+}
+
+impl std::fmt::Display for RankedStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(x) => write!(f, "Unknown({x})"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// Response of sayobot `beatmaplist` command.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BeatmapList {
+    /// 0 means success, -1 means no (more) result.
+    pub status: i32,
+    /// The offset to request the next page with.
+    #[serde(default)]
+    pub endid: u32,
+    #[serde(default)]
+    pub data: Vec<BeatmapSet>,
+}
+
+/// One beatmap set in [`BeatmapList`].
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BeatmapSet {
+    pub sid: u32,
+    pub title: String,
+    #[serde(rename = "titleU", default)]
+    pub title_unicode: String,
+    pub artist: String,
+    #[serde(rename = "artistU", default)]
+    pub artist_unicode: String,
+    pub creator: String,
+    pub approved: RankedStatus,
+    /// Bitmask of game modes: std = 1, taiko = 2, ctb = 4, mania = 8.
+    pub modes: u8,
+    #[serde(default)]
+    pub play_count: u64,
+    #[serde(default)]
+    pub favourite_count: u64,
+    /// Unix timestamp. Only some commands return it.
+    #[serde(default)]
+    pub approved_date: Option<i64>,
+    /// Unix timestamp of the last update.
+    #[serde(default)]
+    pub lastupdate: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bpm: Option<f32>,
+    /// Difficulties of the set. Empty unless filled from [`BeatmapDetail`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub difficulties: Vec<Difficulty>,
+}
+
+/// The [`BeatmapSet::modes`] bitmask of difficulties in the game modes
+/// `modes`, numbered from std = 0 to mania = 3. Unknown modes are ignored.
+pub fn mode_mask(modes: impl IntoIterator<Item = u8>) -> u8 {
+    let bits = modes
+        .into_iter()
+        .filter_map(|mode| 1u32.checked_shl(mode.into()))
+        .fold(0, |acc, bit| acc | bit);
+    GameModes::from_bits_truncate(bits).bits() as u8
+}
+
+impl BeatmapSet {
+    /// Title in unicode if it exists.
+    pub fn display_title(&self) -> &str {
+        if self.title_unicode.is_empty() {
+            &self.title
+        } else {
+            &self.title_unicode
+        }
+    }
+
+    /// Artist in unicode if it exists.
+    pub fn display_artist(&self) -> &str {
+        if self.artist_unicode.is_empty() {
+            &self.artist
+        } else {
+            &self.artist_unicode
+        }
+    }
+
+    /// (min, max) star rating of difficulties.
+    pub fn star_range(&self) -> Option<(f32, f32)> {
+        self.difficulties.iter().fold(None, |acc, d| match acc {
+            None => Some((d.star, d.star)),
+            Some((min, max)) => Some((min.min(d.star), max.max(d.star))),
+        })
+    }
+
+    /// The longest drain length of difficulties, in seconds.
+    pub fn length(&self) -> Option<u32> {
+        self.difficulties.iter().map(|d| d.length).max()
+    }
+
+    /// Unix timestamp the set is ranked at, or updated at if unknown.
+    pub fn date(&self) -> i64 {
+        self.approved_date.unwrap_or(self.lastupdate)
+    }
+
+    /// Fill the details from another response of the same set.
+    pub fn merge(&mut self, detail: BeatmapSet) {
+        self.approved_date = detail.approved_date.or(self.approved_date);
+        self.bpm = detail.bpm.or(self.bpm);
+        self.difficulties = detail.difficulties;
+    }
+}
+
+/// Response data of sayobot `v2/beatmapinfo`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BeatmapDetail {
+    pub sid: u32,
+    pub title: String,
+    #[serde(rename = "titleU", default)]
+    pub title_unicode: String,
+    pub artist: String,
+    #[serde(rename = "artistU", default)]
+    pub artist_unicode: String,
+    pub creator: String,
+    pub approved: RankedStatus,
+    #[serde(default)]
+    pub approved_date: Option<i64>,
+    #[serde(default)]
+    pub last_update: i64,
+    #[serde(default)]
+    pub favourite_count: u64,
+    #[serde(default)]
+    pub bpm: f32,
+    /// 1 if the set contains a video.
+    #[serde(default)]
+    pub video: u8,
+    #[serde(rename = "bid_data", default)]
+    pub difficulties: Vec<Difficulty>,
+}
+
+impl From<BeatmapDetail> for BeatmapSet {
+    fn from(detail: BeatmapDetail) -> Self {
+        Self {
+            sid: detail.sid,
+            title: detail.title,
+            title_unicode: detail.title_unicode,
+            artist: detail.artist,
+            artist_unicode: detail.artist_unicode,
+            creator: detail.creator,
+            approved: detail.approved,
+            modes: mode_mask(detail.difficulties.iter().map(|d| d.mode)),
+            play_count: detail.difficulties.iter().map(|d| d.playcount).sum(),
+            favourite_count: detail.favourite_count,
+            approved_date: detail.approved_date,
+            lastupdate: detail.last_update,
+            bpm: Some(detail.bpm),
+            difficulties: detail.difficulties,
+        }
+    }
+}
+
+/// One difficulty (beatmap) in a set.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Difficulty {
+    pub bid: u32,
+    pub version: String,
+    /// 0 = std, 1 = taiko, 2 = ctb, 3 = mania.
+    pub mode: u8,
+    pub star: f32,
+    #[serde(rename = "AR")]
+    pub ar: f32,
+    #[serde(rename = "OD")]
+    pub od: f32,
+    #[serde(rename = "CS")]
+    pub cs: f32,
+    #[serde(rename = "HP")]
+    pub hp: f32,
+    /// Drain length in seconds.
+    pub length: u32,
+    #[serde(default)]
+    pub maxcombo: u32,
+    #[serde(default)]
+    pub playcount: u64,
+    #[serde(default)]
+    pub passcount: u64,
+}
+
+/// Response envelope of sayobot `v2/beatmapinfo`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BeatmapDetailResponse {
+    pub status: i32,
+    pub data: Option<BeatmapDetail>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_mask() {
+        assert_eq!(mode_mask([0, 3, 3]), 0b1001);
+        assert_eq!(mode_mask([1, 4, 8, 200]), 0b10);
+        assert_eq!(mode_mask([]), 0);
+    }
+
+    #[test]
+    fn test_beatmaplist() {
+        let list: BeatmapList =
+            serde_json::from_str(include_str!("../../test/fixtures/beatmaplist.json")).unwrap();
+        assert_eq!(list.status, 0);
+        assert_eq!(list.endid, 3);
+        assert_eq!(list.data.len(), 3);
+        let set = &list.data[0];
+        assert_eq!(set.sid, 2071043);
+        assert_eq!(set.display_title(), "紅");
+        assert_eq!(set.display_artist(), "かめりあ");
+        assert_eq!(set.approved, RankedStatus::Ranked);
+        assert_eq!(set.modes, 0b1001);
+        assert_eq!(list.data[1].approved, RankedStatus::Loved);
+        assert_eq!(list.data[2].display_title(), "FREEDOM DiVE");
+        assert!(set.difficulties.is_empty());
+    }
+
+    #[test]
+    fn test_beatmaplist_empty() {
+        let list: BeatmapList =
+            serde_json::from_str(include_str!("../../test/fixtures/beatmaplist_empty.json"))
+                .unwrap();
+        assert_eq!(list.status, -1);
+        assert!(list.data.is_empty());
+    }
+
+    #[test]
+    fn test_beatmapinfo() {
+        let res: BeatmapDetailResponse =
+            serde_json::from_str(include_str!("../../test/fixtures/beatmapinfo.json")).unwrap();
+        let detail = res.data.unwrap();
+        assert_eq!(detail.sid, 2071043);
+        assert_eq!(detail.video, 1);
+        assert_eq!(detail.difficulties.len(), 2);
+        let mania = &detail.difficulties[1];
+        assert_eq!(mania.mode, 3);
+        assert_eq!(mania.cs, 7.0);
+        assert_eq!(mania.version, "7K Hard");
+
+        let list: BeatmapList =
+            serde_json::from_str(include_str!("../../test/fixtures/beatmaplist.json")).unwrap();
+        let mut set = list.data[0].clone();
+        set.merge(detail.into());
+        assert_eq!(set.approved_date, Some(1697270400));
+        assert_eq!(set.bpm, Some(218.0));
+        assert_eq!(set.difficulties.len(), 2);
+        assert_eq!(set.star_range(), Some((4.87, 6.42)));
+        assert_eq!(set.length(), Some(161));
+    }
+
+    #[test]
+    fn test_ranked_status() {
+        for i in -3..6 {
+            assert_eq!(i8::from(RankedStatus::from(i)), i);
+        }
+        assert_eq!(RankedStatus::from(-3), RankedStatus::Unknown(-3));
+    }
+}
@@ -0,0 +1,178 @@
+//! Primitive types of the osu! stable database files (`osu!.db`,
+//! `collection.db`), all little-endian.
+
+use std::io::{self, Read, Write};
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read osu! primitive types.
+pub trait ReadExt: Read {
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_bool(&mut self) -> io::Result<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    fn read_i16(&mut self) -> io::Result<i16> {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(i16::from_le_bytes(buf))
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(i32::from_le_bytes(buf))
+    }
+
+    fn read_i64(&mut self) -> io::Result<i64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(i64::from_le_bytes(buf))
+    }
+
+    fn read_f32(&mut self) -> io::Result<f32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(f32::from_le_bytes(buf))
+    }
+
+    fn read_f64(&mut self) -> io::Result<f64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(f64::from_le_bytes(buf))
+    }
+
+    fn read_uleb128(&mut self) -> io::Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("ULEB128 overflows u64".to_string()))
+    }
+
+    /// A string is `0x00` if absent (read as empty), or `0x0b`, ULEB128
+    /// length and UTF-8 bytes.
+    fn read_string(&mut self) -> io::Result<String> {
+        match self.read_u8()? {
+            0x00 => Ok(String::new()),
+            0x0b => {
+                let len = self.read_uleb128()?;
+                let mut buf = vec![];
+                self.take(len).read_to_end(&mut buf)?;
+                if buf.len() as u64 != len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                String::from_utf8(buf).map_err(|e| invalid(e.to_string()))
+            }
+            flag => Err(invalid(format!("invalid string flag {flag:#04x}"))),
+        }
+    }
+
+    /// Skip `n` bytes.
+    fn skip(&mut self, n: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut self.take(n), &mut io::sink())?;
+        if skipped != n {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + ?Sized> ReadExt for R {}
+
+/// Write osu! primitive types.
+pub trait WriteExt: Write {
+    fn write_u8(&mut self, value: u8) -> io::Result<()> {
+        self.write_all(&[value])
+    }
+
+    fn write_bool(&mut self, value: bool) -> io::Result<()> {
+        self.write_u8(value.into())
+    }
+
+    fn write_i16(&mut self, value: i16) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_i32(&mut self, value: i32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_i64(&mut self, value: i64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_f32(&mut self, value: f32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_f64(&mut self, value: f64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_uleb128(&mut self, mut value: u64) -> io::Result<()> {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                return self.write_u8(byte);
+            }
+            self.write_u8(byte | 0x80)?;
+        }
+    }
+
+    /// Write a string, always present even if empty.
+    fn write_string(&mut self, value: &str) -> io::Result<()> {
+        self.write_u8(0x0b)?;
+        self.write_uleb128(value.len() as u64)?;
+        self.write_all(value.as_bytes())
+    }
+}
+
+impl<W: Write + ?Sized> WriteExt for W {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let long = "紅".repeat(100);
+        let mut buf = vec![];
+        buf.write_i32(20211215).unwrap();
+        buf.write_string("").unwrap();
+        buf.write_string(&long).unwrap();
+        buf.write_f64(1.5).unwrap();
+        buf.write_uleb128(624485).unwrap();
+        buf.push(0x00);
+
+        let mut reader = &buf[..];
+        assert_eq!(reader.read_i32().unwrap(), 20211215);
+        assert_eq!(reader.read_string().unwrap(), "");
+        assert_eq!(reader.read_string().unwrap(), long);
+        assert_eq!(reader.read_f64().unwrap(), 1.5);
+        assert_eq!(reader.read_uleb128().unwrap(), 624485);
+        assert_eq!(reader.read_string().unwrap(), "");
+        assert!(reader.read_u8().is_err());
+    }
+
+    #[test]
+    fn test_uleb128() {
+        let mut buf = vec![];
+        buf.write_uleb128(624485).unwrap();
+        assert_eq!(buf, [0xe5, 0x8e, 0x26]);
+        assert!([0x0c].as_slice().read_string().is_err());
+        assert!([0x0b, 0x05, b'a'].as_slice().read_string().is_err());
+    }
+}
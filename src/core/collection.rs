@@ -0,0 +1,214 @@
+//! Reader and writer of `collection.db`, the collections of the osu! stable
+//! client.
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use md5::{Digest, Md5};
+
+use super::binary::{ReadExt, WriteExt};
+
+/// Version written to a new `collection.db`.
+const VERSION: i32 = 20250107;
+
+/// All collections in `collection.db`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionDb {
+    pub version: i32,
+    pub collections: Vec<Collection>,
+}
+
+impl Default for CollectionDb {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            collections: vec![],
+        }
+    }
+}
+
+/// A named group of beatmaps, by MD5 hashes of their `.osu` files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Collection {
+    pub name: String,
+    pub md5s: Vec<String>,
+}
+
+impl CollectionDb {
+    /// Read `path`, or an empty database if it does not exist.
+    pub fn open(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Self::read(BufReader::new(file))
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Write to `path` through a temporary file, so that osu! never sees a
+    /// half written database.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("db.tmp");
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        self.write(&mut file)?;
+        file.into_inner()?.sync_all()?;
+        fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn read(mut r: impl Read) -> Result<Self> {
+        let version = r.read_i32()?;
+        let count = r.read_i32()?;
+        let mut collections = vec![];
+        for _ in 0..count {
+            let name = r.read_string()?;
+            let count = r.read_i32()?;
+            let md5s = (0..count)
+                .map(|_| r.read_string())
+                .collect::<Result<_, _>>()?;
+            collections.push(Collection { name, md5s });
+        }
+        Ok(Self {
+            version,
+            collections,
+        })
+    }
+
+    pub fn write(&self, mut w: impl Write) -> Result<()> {
+        w.write_i32(self.version)?;
+        w.write_i32(self.collections.len() as i32)?;
+        for collection in &self.collections {
+            w.write_string(&collection.name)?;
+            w.write_i32(collection.md5s.len() as i32)?;
+            for md5 in &collection.md5s {
+                w.write_string(md5)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add `collection`, merging into the one with the same name if exists.
+    pub fn merge(&mut self, collection: Collection) {
+        match self
+            .collections
+            .iter_mut()
+            .find(|c| c.name == collection.name)
+        {
+            Some(existing) => {
+                for md5 in collection.md5s {
+                    if !existing.md5s.contains(&md5) {
+                        existing.md5s.push(md5);
+                    }
+                }
+            }
+            None => self.collections.push(collection),
+        }
+    }
+}
+
+/// MD5 hashes of every difficulty (`.osu` file) in an `.osz` or an extracted
+/// folder.
+pub fn beatmap_md5s(path: &Path) -> Result<Vec<String>> {
+    if path.is_dir() {
+        let mut md5s = vec![];
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("osu"))
+            {
+                md5s.push(format!("{:x}", Md5::digest(fs::read(&path)?)));
+            }
+        }
+        return Ok(md5s);
+    }
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut zip = zip::ZipArchive::new(BufReader::new(file))
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let mut md5s = vec![];
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        if !entry.name().to_lowercase().ends_with(".osu") {
+            continue;
+        }
+        let mut content = vec![];
+        entry.read_to_end(&mut content)?;
+        md5s.push(format!("{:x}", Md5::digest(&content)));
+    }
+    Ok(md5s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(name: &str, md5s: &[&str]) -> Collection {
+        Collection {
+            name: name.to_string(),
+            md5s: md5s.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let db = CollectionDb {
+            version: 20211215,
+            collections: vec![
+                collection("Hot 2026-10-18 mania", &["a", "b"]),
+                collection("空", &[]),
+            ],
+        };
+        let mut buf = vec![];
+        db.write(&mut buf).unwrap();
+        assert_eq!(CollectionDb::read(&buf[..]).unwrap(), db);
+        assert!(CollectionDb::read(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_merge_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("collection.db");
+        let mut db = CollectionDb::open(&path).unwrap();
+        assert!(db.collections.is_empty());
+        db.merge(collection("old", &["a"]));
+        db.save(&path).unwrap();
+
+        let mut db = CollectionDb::open(&path).unwrap();
+        db.merge(collection("old", &["a", "b"]));
+        db.merge(collection("new", &["c"]));
+        db.save(&path).unwrap();
+        assert_eq!(
+            CollectionDb::open(&path).unwrap().collections,
+            [collection("old", &["a", "b"]), collection("new", &["c"])]
+        );
+        assert!(!path.with_extension("db.tmp").exists());
+    }
+
+    #[test]
+    fn test_beatmap_md5s() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("1.osz");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, content) in [("a [Hard].osu", "hard"), ("audio.mp3", ""), ("b.OSU", "")] {
+            zip.start_file(name, Default::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let md5s = [
+            "d64a84456adc959f56de6af685d0dadd",
+            "d41d8cd98f00b204e9800998ecf8427e",
+        ];
+        assert_eq!(beatmap_md5s(&path).unwrap(), md5s);
+
+        let folder = dir.path().join("1 Artist - Title");
+        fs::create_dir(&folder).unwrap();
+        fs::write(folder.join("a [Hard].osu"), "hard").unwrap();
+        fs::write(folder.join("audio.mp3"), "").unwrap();
+        assert_eq!(beatmap_md5s(&folder).unwrap(), md5s[..1]);
+    }
+}
@@ -0,0 +1,198 @@
+//! Ranges of difficulty values checked on the returned sets, for filters the
+//! mirrors do not support.
+
+use std::{fmt, str::FromStr};
+
+use clap::Args;
+
+use super::search::{parse_length, Field, Filter, Op, Value};
+
+/// An inclusive range written like `5..7`, `5..`, `..7` or `7`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Range {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+impl Range {
+    /// Parse a range of values parsed by `parse`.
+    fn parse_with(s: &str, parse: impl Fn(&str) -> Option<f32>) -> Result<Self, String> {
+        let s = s.trim();
+        let bound = |s: &str| match s.trim() {
+            "" => Ok(None),
+            value => parse(value)
+                .filter(|value| value.is_finite())
+                .map(Some)
+                .ok_or_else(|| format!("invalid value `{value}`")),
+        };
+        let range = match s.split_once("..") {
+            Some((min, max)) => Self {
+                min: bound(min)?,
+                max: bound(max)?,
+            },
+            None => {
+                let value = bound(s)?;
+                Self {
+                    min: value,
+                    max: value,
+                }
+            }
+        };
+        match range {
+            Self {
+                min: Some(min),
+                max: Some(max),
+            } if max < min => Err(format!("range `{s}` ends before it starts")),
+            range => Ok(range),
+        }
+    }
+
+    /// Parse a range of seconds, also taking lengths like `2:30`.
+    pub fn parse_length(s: &str) -> Result<Self, String> {
+        Self::parse_with(s, parse_length)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    /// Search filters of `field` within the range.
+    fn filters(&self, field: Field) -> impl Iterator<Item = Filter> {
+        [(self.min, Op::Ge), (self.max, Op::Le)]
+            .into_iter()
+            .filter_map(move |(bound, op)| {
+                Some(Filter {
+                    field,
+                    op,
+                    value: Value::Number(bound?),
+                })
+            })
+    }
+}
+
+impl FromStr for Range {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Self::parse_with(s, |value| value.parse().ok())
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if min == max => write!(f, "{min}"),
+            (min, max) => {
+                if let Some(min) = min {
+                    write!(f, "{min}")?;
+                }
+                f.write_str("..")?;
+                if let Some(max) = max {
+                    write!(f, "{max}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Ranges the sets and their difficulties must be in. A set is kept if one of
+/// its difficulties is in all the ranges, with only such difficulties.
+#[derive(Args, Debug, Clone, Default, PartialEq)]
+pub struct Filters {
+    #[arg(long, help = t!("cli.stars"))]
+    pub stars: Option<Range>,
+    #[arg(long, help = t!("cli.bpm"))]
+    pub bpm: Option<Range>,
+    /// Drain length in seconds.
+    #[arg(long, value_parser = Range::parse_length, help = t!("cli.length"))]
+    pub length: Option<Range>,
+    #[arg(long, help = t!("cli.ar"))]
+    pub ar: Option<Range>,
+    #[arg(long, help = t!("cli.od"))]
+    pub od: Option<Range>,
+    #[arg(long, help = t!("cli.cs"))]
+    pub cs: Option<Range>,
+    #[arg(long, help = t!("cli.hp"))]
+    pub hp: Option<Range>,
+    /// Key count of mania difficulties.
+    #[arg(long, help = t!("cli.keys"))]
+    pub keys: Option<Range>,
+}
+
+impl Filters {
+    /// The ranges with their fields, in the order of the TUI panel.
+    pub fn ranges(&self) -> [(Field, Option<Range>); 8] {
+        [
+            (Field::Stars, self.stars),
+            (Field::Bpm, self.bpm),
+            (Field::Length, self.length),
+            (Field::Ar, self.ar),
+            (Field::Od, self.od),
+            (Field::Cs, self.cs),
+            (Field::Hp, self.hp),
+            (Field::Keys, self.keys),
+        ]
+    }
+
+    /// The range of `field`.
+    pub fn range_mut(&mut self, field: Field) -> Option<&mut Option<Range>> {
+        Some(match field {
+            Field::Stars => &mut self.stars,
+            Field::Bpm => &mut self.bpm,
+            Field::Length => &mut self.length,
+            Field::Ar => &mut self.ar,
+            Field::Od => &mut self.od,
+            Field::Cs => &mut self.cs,
+            Field::Hp => &mut self.hp,
+            Field::Keys => &mut self.keys,
+            Field::Creator | Field::Status | Field::Mode => return None,
+        })
+    }
+
+    /// The ranges as search filters.
+    pub fn to_filters(&self) -> Vec<Filter> {
+        self.ranges()
+            .into_iter()
+            .filter_map(|(field, range)| Some(range?.filters(field)))
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range() {
+        let range: Range = "5..7.5".parse().unwrap();
+        assert_eq!((range.min, range.max), (Some(5.0), Some(7.5)));
+        assert_eq!(range.to_string(), "5..7.5");
+        assert_eq!("..7".parse::<Range>().unwrap().min, None);
+        assert_eq!("7".parse::<Range>().unwrap().to_string(), "7");
+        assert!("..".parse::<Range>().unwrap().is_empty());
+        assert_eq!(Range::parse_length("1:30..").unwrap().min, Some(90.0));
+        for s in ["x", "7..5", "1:30", "nan", "inf..", "-inf..5", "..NaN"] {
+            assert!(s.parse::<Range>().is_err(), "{s}");
+        }
+        for s in ["inf", "2:00..1:00"] {
+            assert!(Range::parse_length(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_to_filters() {
+        let filters = Filters {
+            stars: Some("5..".parse().unwrap()),
+            keys: Some("7".parse().unwrap()),
+            ..Default::default()
+        };
+        let filters: Vec<_> = filters
+            .to_filters()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(filters, ["stars>=5", "keys>=7", "keys<=7"]);
+    }
+}
@@ -0,0 +1,190 @@
+//! Index of beatmap sets already in the local osu! `Songs` directory.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use super::osu_db::OsuDb;
+
+/// Beatmap sets owned locally, by set id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Library {
+    sets: HashMap<u32, PathBuf>,
+}
+
+impl Library {
+    /// Load the sets in the `Songs` directory of osu!, from the `osu!.db` next
+    /// to it if readable, otherwise by scanning the directory.
+    pub fn load(songs: &Path) -> Result<Self> {
+        let db = songs.parent().map(|dir| dir.join("osu!.db"));
+        match db.filter(|db| db.is_file()).map(|db| OsuDb::open(&db)) {
+            Some(Ok(db)) => Ok(Self::from_osu_db(&db, songs)),
+            Some(Err(e)) => {
+                tracing::warn!(error = %format!("{e:#}"), "fall back to scanning Songs");
+                Self::scan(songs)
+            }
+            None => Self::scan(songs),
+        }
+    }
+
+    /// Sets in `osu!.db`, whose folders are in `songs`.
+    pub fn from_osu_db(db: &OsuDb, songs: &Path) -> Self {
+        let mut library = Self::default();
+        for beatmap in &db.beatmaps {
+            if let Ok(sid @ 1..) = u32::try_from(beatmap.sid) {
+                library.insert(sid, songs.join(&beatmap.folder));
+            }
+        }
+        library
+    }
+
+    /// Scan the `Songs` directory of osu!.
+    ///
+    /// A set is owned if its folder (or unimported `.osz`) is named like
+    /// `123456 Artist - Title`, or any `.osu` file in its folder has a
+    /// `BeatmapSetID`.
+    pub fn scan(dir: &Path) -> Result<Self> {
+        let mut library = Self::default();
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let sid = if path.is_dir() {
+                path.file_name()
+                    .and_then(|name| parse_sid(&name.to_string_lossy()))
+                    .or_else(|| folder_sid(&path))
+            } else if path.extension().is_some_and(|ext| ext == "osz") {
+                path.file_stem()
+                    .and_then(|name| parse_sid(&name.to_string_lossy()))
+            } else {
+                None
+            };
+            if let Some(sid) = sid {
+                library.insert(sid, path);
+            }
+        }
+        tracing::debug!(dir = %dir.display(), sets = library.len(), "library scanned");
+        Ok(library)
+    }
+
+    pub fn contains(&self, sid: u32) -> bool {
+        self.sets.contains_key(&sid)
+    }
+
+    /// Where the set is stored.
+    pub fn get(&self, sid: u32) -> Option<&Path> {
+        self.sets.get(&sid).map(PathBuf::as_path)
+    }
+
+    pub fn insert(&mut self, sid: u32, path: PathBuf) {
+        self.sets.insert(sid, path);
+    }
+
+    pub fn len(&self) -> usize {
+        self.sets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+}
+
+/// The leading set id of a name like `123456 Artist - Title`.
+fn parse_sid(name: &str) -> Option<u32> {
+    let id = name.split_once(' ').map_or(name, |(id, _)| id);
+    id.parse().ok().filter(|&sid| sid > 0)
+}
+
+/// The first `BeatmapSetID` of the `.osu` files in `dir`.
+fn folder_sid(dir: &Path) -> Option<u32> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "osu"))
+        .find_map(|path| osu_sid(&path))
+}
+
+/// `BeatmapSetID` in the `[Metadata]` section of a `.osu` file. Unsubmitted
+/// maps have -1. Only the lines up to it are read, not the whole file,
+/// as scanning reads one of every folder in `Songs`.
+fn osu_sid(path: &Path) -> Option<u32> {
+    let reader = BufReader::new(File::open(path).ok()?);
+    for line in reader.split(b'\n') {
+        let line = line.ok()?;
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("BeatmapSetID:") {
+            return value.trim().parse().ok().filter(|&sid| sid > 0);
+        }
+        // [Metadata] comes before these sections
+        if line == "[Difficulty]" || line == "[Events]" {
+            break;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan() {
+        let dir = Path::new("test/fixtures/Songs");
+        let library = Library::scan(dir).unwrap();
+        assert_eq!(library.len(), 3);
+        assert_eq!(
+            library.get(2071043),
+            Some(dir.join("2071043 Camellia - Kurenai").as_path())
+        );
+        assert_eq!(
+            library.get(2069820),
+            Some(dir.join("Renamed Idol").as_path())
+        );
+        assert!(library.contains(2070511));
+        assert!(Library::scan(&dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_load_osu_db() {
+        use crate::core::osu_db::tests::{beatmap, write_db};
+
+        let dir = tempfile::tempdir().unwrap();
+        let songs = dir.path().join("Songs");
+        fs::create_dir(&songs).unwrap();
+        fs::create_dir(songs.join("1 folder name")).unwrap();
+        let library = Library::load(&songs).unwrap();
+        assert!(library.contains(1));
+
+        let db = write_db(
+            20250107,
+            &[
+                beatmap(2071043, 1, "Kurenai"),
+                beatmap(2071043, 2, "Kurenai"),
+                beatmap(-1, 0, "unsubmitted"),
+            ],
+        );
+        fs::write(dir.path().join("osu!.db"), db).unwrap();
+        let library = Library::load(&songs).unwrap();
+        assert_eq!(library.len(), 1);
+        assert_eq!(library.get(2071043), Some(songs.join("Kurenai").as_path()));
+
+        // a broken osu!.db falls back to scanning
+        fs::write(dir.path().join("osu!.db"), [1, 2, 3]).unwrap();
+        let library = Library::load(&songs).unwrap();
+        assert!(library.contains(1));
+    }
+
+    #[test]
+    fn test_parse_sid() {
+        assert_eq!(parse_sid("123456 Artist - Title"), Some(123456));
+        assert_eq!(parse_sid("123456"), Some(123456));
+        assert_eq!(parse_sid("beatmap-638412345-unsubmitted"), None);
+        assert_eq!(parse_sid("-1 Artist - Title"), None);
+    }
+}
@@ -0,0 +1,644 @@
+pub mod osu_v2;
+pub mod sayobot;
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use futures::{future::BoxFuture, stream::FuturesOrdered, FutureExt, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use super::{
+    api::{ListKind, API},
+    beatmap::{BeatmapList, BeatmapSet},
+    pack::{Pack, PackKind, PackPage},
+    search::SearchQuery,
+};
+use osu_v2::{Catboy, Nerinyan};
+use sayobot::Sayobot;
+
+/// A beatmap mirror site.
+pub trait Mirror: fmt::Debug + Send + Sync {
+    /// Name shown to users.
+    fn name(&self) -> &str;
+
+    /// Request a beatmap list.
+    fn list<'a>(&'a self, client: &'a Client, api: &'a API) -> BoxFuture<'a, Result<BeatmapList>>;
+
+    /// Request one beatmap set with all its difficulties.
+    fn beatmap_set<'a>(
+        &'a self,
+        _client: &'a Client,
+        _sid: u32,
+    ) -> BoxFuture<'a, Result<BeatmapSet>> {
+        async { Err(Unsupported.into()) }.boxed()
+    }
+
+    /// Request a page of the official beatmap packs of `kind`, the first page
+    /// if `cursor` is `None`.
+    fn packs<'a>(
+        &'a self,
+        _client: &'a Client,
+        _kind: PackKind,
+        _cursor: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<PackPage>> {
+        async { Err(Unsupported.into()) }.boxed()
+    }
+
+    /// Request a beatmap pack by its tag, with the ids of its sets.
+    fn pack<'a>(&'a self, _client: &'a Client, _tag: &'a str) -> BoxFuture<'a, Result<Pack>> {
+        async { Err(Unsupported.into()) }.boxed()
+    }
+
+    /// The url to download the `.osz` of a beatmap set, or `None` if the
+    /// mirror does not offer the variant.
+    fn download_url(&self, sid: u32, variant: Variant) -> Option<String>;
+}
+
+/// Which files of a beatmap set to download.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Variant {
+    #[default]
+    Full,
+    /// Without the video.
+    NoVideo,
+    /// Without the video and storyboard.
+    Mini,
+}
+
+/// The mirror does not support the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsupported;
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported by the mirror")
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Most pages requested by one filtered query, so that strict filters do not
+/// page through a whole mirror.
+const FILTER_PAGES: usize = 10;
+
+/// Requests of difficulties in flight while filtering.
+const DETAIL_REQUESTS: usize = 4;
+
+/// Most requests of difficulties made by one filtered query, for lists
+/// leaving them out.
+const FILTER_DETAILS: usize = 100;
+
+/// A mirror is moved behind all healthy mirrors after this many consecutive
+/// failures.
+const UNHEALTHY_FAILURES: u32 = 3;
+
+#[derive(Debug)]
+struct Entry {
+    mirror: Box<dyn Mirror>,
+    /// Consecutive failures.
+    failures: AtomicU32,
+}
+
+/// Mirrors in priority order, with health tracking for failover.
+#[derive(Debug)]
+pub struct Mirrors {
+    entries: Vec<Entry>,
+}
+
+impl Default for Mirrors {
+    fn default() -> Self {
+        Self::new(vec![
+            Box::new(Sayobot::default()),
+            Box::new(Catboy),
+            Box::new(Nerinyan),
+        ])
+    }
+}
+
+impl Mirrors {
+    /// Constructs a new instance of [`Mirrors`], the first one has the
+    /// highest priority.
+    pub fn new(mirrors: Vec<Box<dyn Mirror>>) -> Self {
+        Self {
+            entries: mirrors
+                .into_iter()
+                .map(|mirror| Entry {
+                    mirror,
+                    failures: AtomicU32::new(0),
+                })
+                .collect(),
+        }
+    }
+
+    /// Only the sayobot compatible mirror at `url` if given, otherwise the
+    /// builtin mirrors.
+    pub fn from_url(url: Option<&str>) -> Self {
+        match url {
+            Some(url) => Self::new(vec![Box::new(Sayobot::with_base(url))]),
+            None => Self::default(),
+        }
+    }
+
+    /// Mirrors by builtin names (`sayobot`, `catboy`, `nerinyan`) or urls of
+    /// sayobot compatible mirrors, the builtin ones if `names` is empty.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self> {
+        if names.is_empty() {
+            return Ok(Self::default());
+        }
+        let mut mirrors: Vec<Box<dyn Mirror>> = vec![];
+        for name in names {
+            mirrors.push(match name.as_ref() {
+                "sayobot" => Box::new(Sayobot::default()),
+                "catboy" => Box::new(Catboy),
+                "nerinyan" => Box::new(Nerinyan),
+                url if url.starts_with("http://") || url.starts_with("https://") => {
+                    Box::new(Sayobot::with_base(url))
+                }
+                name => bail!("unknown mirror `{name}`, expected a builtin name or an url"),
+            });
+        }
+        Ok(Self::new(mirrors))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> &dyn Mirror {
+        self.entries[index].mirror.as_ref()
+    }
+
+    pub fn is_healthy(&self, index: usize) -> bool {
+        self.entries[index].failures.load(Ordering::Relaxed) < UNHEALTHY_FAILURES
+    }
+
+    /// Indices of mirrors in failover order: healthy mirrors by priority,
+    /// then unhealthy ones.
+    pub fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by_key(|&i| !self.is_healthy(i));
+        order
+    }
+
+    pub fn report_success(&self, index: usize) {
+        self.entries[index].failures.store(0, Ordering::Relaxed);
+    }
+
+    pub fn report_failure(&self, index: usize) {
+        self.entries[index].failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Request one page of a beatmap list, failing over to the next mirror on
+    /// error and skipping mirrors that cannot sort it as asked. The sets are
+    /// not checked against `api.search`, see [`Mirrors::query`].
+    pub async fn list(&self, client: &Client, api: &API) -> Result<BeatmapList> {
+        self.failover("list", |mirror| mirror.list(client, api))
+            .await
+    }
+
+    /// Request a beatmap list. If `api.since` is set, page through the newest
+    /// sets until the date instead, returning every set matching the game
+    /// modes and map states in one list. With `api.search`, sets not passing
+    /// its filters are dropped, and pages are requested until `api.limit` sets
+    /// pass, or at most [`FILTER_PAGES`] pages and [`FILTER_DETAILS`]
+    /// difficulty requests are made. The list then ends after the last set
+    /// checked, so that the next page continues from there. Sets since the
+    /// date left unchecked once the difficulty requests run out are dropped.
+    pub async fn query(&self, client: &Client, api: &API) -> Result<BeatmapList> {
+        if let Some(since) = api.since {
+            return self.query_since(client, api, since).await;
+        }
+        let Some(search) = &api.search else {
+            return self.list(client, api).await;
+        };
+        let limit = api.limit as usize;
+        let mut api = api.clone();
+        let mut sets = vec![];
+        let mut details = FILTER_DETAILS;
+        for _ in 0..FILTER_PAGES {
+            let offset = api.offset.unwrap_or(0);
+            let list = self.list(client, &api).await?;
+            let (len, more) = (list.data.len(), list.status == 0 && list.endid > offset);
+            let (passed, checked) = self
+                .filter(client, search, list.data, limit - sets.len(), &mut details)
+                .await;
+            sets.extend(passed);
+            if checked < len {
+                if sets.len() < limit {
+                    tracing::warn!(
+                        requests = FILTER_DETAILS,
+                        "stopped filtering at the most difficulty requests"
+                    );
+                }
+                return Ok(BeatmapList {
+                    status: 0,
+                    endid: offset + checked as u32,
+                    data: sets,
+                });
+            }
+            if !more || sets.len() >= limit {
+                return Ok(BeatmapList {
+                    status: if more { 0 } else { -1 },
+                    endid: list.endid,
+                    data: sets,
+                });
+            }
+            api.offset = Some(list.endid);
+        }
+        tracing::warn!(pages = FILTER_PAGES, "stopped filtering at the most pages");
+        Ok(BeatmapList {
+            status: 0,
+            endid: api.offset.unwrap_or(0),
+            data: sets,
+        })
+    }
+
+    async fn query_since(&self, client: &Client, api: &API, since: Date) -> Result<BeatmapList> {
+        let since = since.midnight().assume_utc().unix_timestamp();
+        let mut api = api.clone();
+        api.type_ = Some(ListKind::New);
+        api.since = None;
+        let mut sets = vec![];
+        loop {
+            let list = self.list(client, &api).await?;
+            // stop at a page older than the date, so that a few sets out of
+            // order do not end the paging early
+            if !list.data.iter().any(|set| set.date() >= since) {
+                break;
+            }
+            sets.extend(
+                list.data
+                    .into_iter()
+                    .filter(|set| set.date() >= since && api.matches(set)),
+            );
+            if list.status != 0 || list.endid <= api.offset.unwrap_or(0) {
+                break;
+            }
+            api.offset = Some(list.endid);
+        }
+        if let Some(search) = &api.search {
+            let len = sets.len();
+            let mut details = FILTER_DETAILS;
+            let checked;
+            (sets, checked) = self
+                .filter(client, search, sets, usize::MAX, &mut details)
+                .await;
+            if checked < len {
+                tracing::warn!(
+                    requests = FILTER_DETAILS,
+                    unchecked = len - checked,
+                    "stopped filtering at the most difficulty requests, dropping the sets left"
+                );
+            }
+        }
+        Ok(BeatmapList {
+            status: -1,
+            endid: sets.len() as u32,
+            data: sets,
+        })
+    }
+
+    /// Keep up to `limit` sets passing the filters of `search`, with only
+    /// their passing difficulties, and count the sets checked from the start.
+    /// Difficulties left out of lists are requested first if the filters need
+    /// them, taking from the `details` budget; sets whose difficulties cannot
+    /// be requested are dropped, and checking stops once the budget runs out.
+    async fn filter(
+        &self,
+        client: &Client,
+        search: &SearchQuery,
+        sets: Vec<BeatmapSet>,
+        limit: usize,
+        details: &mut usize,
+    ) -> (Vec<BeatmapSet>, usize) {
+        let needs_difficulties = search.needs_difficulties();
+        let check = |set: BeatmapSet, fetch: bool| async move {
+            let mut set = if fetch {
+                match self.beatmap_set(client, set.sid).await {
+                    Ok(detail) => BeatmapSet {
+                        bpm: set.bpm.or(detail.bpm),
+                        difficulties: detail.difficulties,
+                        ..set
+                    },
+                    Err(e) => {
+                        tracing::warn!(sid = set.sid, error = %e, "difficulties unavailable");
+                        return None;
+                    }
+                }
+            } else {
+                set
+            };
+            search.retain(&mut set).then_some(set)
+        };
+        let mut sets = sets.into_iter();
+        let mut checks = FuturesOrdered::new();
+        let (mut passed, mut checked, mut exhausted) = (vec![], 0, false);
+        while passed.len() < limit {
+            // no more checks in flight than sets still needed, so that none
+            // is wasted once `limit` sets pass
+            while !exhausted && checks.len() < DETAIL_REQUESTS.min(limit - passed.len()) {
+                let Some(set) = sets.next() else {
+                    break;
+                };
+                let fetch = needs_difficulties && set.difficulties.is_empty();
+                if fetch && *details == 0 {
+                    exhausted = true;
+                    break;
+                }
+                if fetch {
+                    *details -= 1;
+                }
+                checks.push_back(check(set, fetch));
+            }
+            let Some(set) = checks.next().await else {
+                break;
+            };
+            checked += 1;
+            passed.extend(set);
+        }
+        (passed, checked)
+    }
+
+    /// Request one beatmap set with all its difficulties, failing over to the
+    /// next mirror on error.
+    pub async fn beatmap_set(&self, client: &Client, sid: u32) -> Result<BeatmapSet> {
+        self.failover("beatmap set", |mirror| mirror.beatmap_set(client, sid))
+            .await
+    }
+
+    /// Request every official beatmap pack of `kind`, failing over to the
+    /// next mirror on error. Only catboy serves packs among the builtin
+    /// mirrors.
+    pub async fn packs(&self, client: &Client, kind: PackKind) -> Result<Vec<Pack>> {
+        let mut packs = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = self
+                .failover("beatmap packs", |mirror| {
+                    mirror.packs(client, kind, cursor.as_deref())
+                })
+                .await?;
+            packs.extend(page.packs);
+            match page.cursor {
+                Some(next) if cursor.as_ref() != Some(&next) => cursor = Some(next),
+                _ => break,
+            }
+        }
+        Ok(packs)
+    }
+
+    /// Request a beatmap pack by its tag, failing over to the next mirror on
+    /// error. Only catboy serves packs among the builtin mirrors.
+    pub async fn pack(&self, client: &Client, tag: &str) -> Result<Pack> {
+        self.failover("beatmap pack", |mirror| mirror.pack(client, tag))
+            .await
+            .with_context(|| format!("pack {tag}"))
+    }
+
+    /// Send a request by `request` to mirrors in failover order until one
+    /// succeeds, skipping mirrors not supporting it.
+    async fn failover<'a, T>(
+        &'a self,
+        what: &str,
+        request: impl Fn(&'a dyn Mirror) -> BoxFuture<'a, Result<T>>,
+    ) -> Result<T> {
+        let mut last_error = anyhow!("no mirror supports the {what} request");
+        for index in self.order() {
+            match request(self.get(index)).await {
+                Ok(value) => {
+                    self.report_success(index);
+                    return Ok(value);
+                }
+                Err(e) if e.is::<Unsupported>() => {}
+                Err(e) => {
+                    tracing::warn!(mirror = self.get(index).name(), error = %e, "{what} failed");
+                    self.report_failure(index);
+                    last_error = e.context(format!("mirror {}", self.get(index).name()));
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        api::{parse_date, GameModes, RankStatus},
+        beatmap::{Difficulty, RankedStatus},
+    };
+    use std::sync::Arc;
+
+    /// A mirror listing pages of fixed sets, counting the sets requested. An
+    /// offset within a page lists the rest of it.
+    #[derive(Debug)]
+    struct Pages(Vec<Vec<BeatmapSet>>, Arc<AtomicU32>);
+
+    impl Mirror for Pages {
+        fn name(&self) -> &str {
+            "pages"
+        }
+
+        fn list<'a>(&'a self, _: &'a Client, api: &'a API) -> BoxFuture<'a, Result<BeatmapList>> {
+            let offset = api.offset.unwrap_or(0);
+            let mut start = 0;
+            let mut data = vec![];
+            for page in &self.0 {
+                if (offset as usize) < start + page.len() {
+                    data = page[offset as usize - start..].to_vec();
+                    break;
+                }
+                start += page.len();
+            }
+            let list = BeatmapList {
+                status: if data.is_empty() { -1 } else { 0 },
+                endid: offset + data.len() as u32,
+                data,
+            };
+            async { Ok(list) }.boxed()
+        }
+
+        /// A set with one difficulty of `sid` stars, failing for set 4.
+        fn beatmap_set<'a>(&'a self, _: &'a Client, sid: u32) -> BoxFuture<'a, Result<BeatmapSet>> {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            if sid == 4 {
+                return async { bail!("set 4 unavailable") }.boxed();
+            }
+            let difficulty = Difficulty {
+                star: sid as f32,
+                ..Default::default()
+            };
+            let set = BeatmapSet {
+                sid,
+                difficulties: vec![difficulty],
+                ..set(sid, "2024-01-01", 1, RankedStatus::Ranked)
+            };
+            async { Ok(set) }.boxed()
+        }
+
+        fn download_url(&self, sid: u32, _: Variant) -> Option<String> {
+            Some(sid.to_string())
+        }
+    }
+
+    fn set(sid: u32, date: &str, modes: u8, approved: RankedStatus) -> BeatmapSet {
+        let list: BeatmapList =
+            serde_json::from_str(include_str!("../../../test/fixtures/beatmaplist.json")).unwrap();
+        BeatmapSet {
+            sid,
+            modes,
+            approved,
+            approved_date: Some(
+                parse_date(date)
+                    .unwrap()
+                    .midnight()
+                    .assume_utc()
+                    .unix_timestamp(),
+            ),
+            ..list.data[0].clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_since() {
+        let mirrors = Mirrors::new(vec![Box::new(Pages(
+            vec![
+                vec![
+                    set(1, "2024-03-01", 1, RankedStatus::Ranked),
+                    set(2, "2024-02-20", 8, RankedStatus::Ranked),
+                ],
+                vec![
+                    set(3, "2024-02-10", 1, RankedStatus::Graveyard),
+                    set(4, "2024-01-01", 1, RankedStatus::Loved),
+                    set(5, "2024-02-02", 1, RankedStatus::Loved),
+                ],
+                vec![set(6, "2024-01-20", 1, RankedStatus::Ranked)],
+                vec![set(7, "2024-03-01", 1, RankedStatus::Ranked)],
+            ],
+            Default::default(),
+        ))]);
+        let mut api = API::new(2);
+        api.mode = Some(GameModes::STD);
+        api.class = Some(RankStatus::RANKED | RankStatus::QUALIFIED | RankStatus::LOVED);
+        api.since = Some(parse_date("2024-02-01").unwrap());
+
+        let list = mirrors.query(&Client::new(), &api).await.unwrap();
+        assert_eq!(
+            list.data.iter().map(|set| set.sid).collect::<Vec<_>>(),
+            [1, 5]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_since_filtered() {
+        let requests = Arc::new(AtomicU32::new(0));
+        let mirrors = Mirrors::new(vec![Box::new(Pages(
+            vec![(10..=FILTER_DETAILS as u32 + 19)
+                .map(|sid| set(sid, "2024-03-01", 1, RankedStatus::Ranked))
+                .collect()],
+            requests.clone(),
+        ))]);
+        let mut api = API::new(2);
+        api.since = Some(parse_date("2024-02-01").unwrap());
+        api.search = Some("stars>=1".parse().unwrap());
+
+        // the sets left once the difficulty requests run out are dropped
+        let list = mirrors.query(&Client::new(), &api).await.unwrap();
+        assert_eq!(list.data.len(), FILTER_DETAILS);
+        assert_eq!(requests.load(Ordering::Relaxed), FILTER_DETAILS as u32);
+    }
+
+    #[tokio::test]
+    async fn test_query_filtered() {
+        let sets = |sids: &[u32]| -> Vec<_> {
+            sids.iter()
+                .map(|&sid| set(sid, "2024-01-01", 1, RankedStatus::Ranked))
+                .collect()
+        };
+        let requests = Arc::new(AtomicU32::new(0));
+        let mirrors = Mirrors::new(vec![Box::new(Pages(
+            vec![sets(&[1, 2, 3]), sets(&[4, 5]), sets(&[6, 7])],
+            requests.clone(),
+        ))]);
+        let sids = |list: &BeatmapList| list.data.iter().map(|set| set.sid).collect::<Vec<_>>();
+        let mut api = API::new(1);
+        api.search = Some("stars>=2 stars<=5".parse().unwrap());
+
+        // the list ends after the last set checked
+        let list = mirrors.query(&Client::new(), &api).await.unwrap();
+        assert_eq!(sids(&list), [2]);
+        assert_eq!((list.status, list.endid), (0, 2));
+        assert_eq!(list.data[0].difficulties.len(), 1);
+        // no difficulties requested past the set filling the limit
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+        api.offset = Some(list.endid);
+        let list = mirrors.query(&Client::new(), &api).await.unwrap();
+        assert_eq!(sids(&list), [3]);
+        assert_eq!((list.status, list.endid), (0, 3));
+
+        // pages without passing sets are skipped
+        api.search = Some("stars>=7".parse().unwrap());
+        let list = mirrors.query(&Client::new(), &api).await.unwrap();
+        assert_eq!(sids(&list), [7]);
+        assert_eq!((list.status, list.endid), (0, 7));
+
+        api.search = Some("stars>=2 stars<=5".parse().unwrap());
+        api.offset = None;
+        api.limit = 10;
+        let list = mirrors.query(&Client::new(), &api).await.unwrap();
+        assert_eq!(sids(&list), [2, 3, 5]);
+        assert_eq!(list.status, -1);
+    }
+
+    #[test]
+    fn test_order() {
+        let mirrors = Mirrors::from_url(None);
+        assert_eq!(mirrors.len(), 3);
+        assert_eq!(mirrors.order(), [0, 1, 2]);
+        for _ in 0..UNHEALTHY_FAILURES {
+            mirrors.report_failure(0);
+        }
+        assert_eq!(mirrors.order(), [1, 2, 0]);
+        mirrors.report_success(0);
+        assert_eq!(mirrors.order(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_from_names() {
+        let mirrors = Mirrors::from_names(&["catboy", "http://localhost:5000/"]).unwrap();
+        assert_eq!(mirrors.len(), 2);
+        assert_eq!(mirrors.get(0).name(), "catboy");
+        assert_eq!(
+            mirrors.get(1).download_url(1, Variant::Full).unwrap(),
+            "http://localhost:5000/beatmaps/download/full/1"
+        );
+        assert_eq!(Mirrors::from_names::<&str>(&[]).unwrap().len(), 3);
+        assert!(Mirrors::from_names(&["example.com"]).is_err());
+    }
+
+    #[test]
+    fn test_from_url() {
+        let mirrors = Mirrors::from_url(Some("http://localhost:5000/"));
+        assert_eq!(mirrors.len(), 1);
+        assert_eq!(
+            mirrors.get(0).download_url(1, Variant::Full).unwrap(),
+            "http://localhost:5000/beatmaps/download/full/1"
+        );
+        assert_eq!(
+            mirrors.get(0).download_url(1, Variant::Mini).unwrap(),
+            "http://localhost:5000/beatmaps/download/mini/1"
+        );
+    }
+}
@@ -0,0 +1,453 @@
+//! Mirrors whose search api returns beatmap sets in the format of osu! api v2.
+
+use anyhow::{bail, Context, Result};
+use futures::{future::BoxFuture, FutureExt};
+use reqwest::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::{Mirror, Unsupported, Variant};
+use crate::core::{
+    api::{ListKind, RankStatus, API},
+    beatmap::{mode_mask, BeatmapList, BeatmapSet, Difficulty, RankedStatus},
+    pack::{Pack, PackKind, PackPage},
+};
+
+#[derive(Debug, Deserialize)]
+struct V2Set {
+    id: u32,
+    title: String,
+    #[serde(default)]
+    title_unicode: Option<String>,
+    artist: String,
+    #[serde(default)]
+    artist_unicode: Option<String>,
+    creator: String,
+    status: String,
+    #[serde(default)]
+    play_count: u64,
+    #[serde(default)]
+    favourite_count: u64,
+    #[serde(default)]
+    ranked_date: Option<String>,
+    #[serde(default)]
+    last_updated: Option<String>,
+    #[serde(default)]
+    bpm: Option<f32>,
+    #[serde(default)]
+    beatmaps: Vec<V2Beatmap>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Beatmap {
+    id: u32,
+    version: String,
+    mode_int: u8,
+    difficulty_rating: f32,
+    ar: f32,
+    accuracy: f32,
+    cs: f32,
+    drain: f32,
+    hit_length: u32,
+    #[serde(default)]
+    max_combo: Option<u32>,
+    #[serde(default)]
+    playcount: u64,
+    #[serde(default)]
+    passcount: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Packs {
+    beatmap_packs: Vec<V2Pack>,
+    #[serde(default)]
+    cursor_string: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2Pack {
+    tag: String,
+    name: String,
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    beatmapsets: Vec<V2PackSet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct V2PackSet {
+    id: u32,
+}
+
+impl From<V2Pack> for Pack {
+    fn from(pack: V2Pack) -> Self {
+        Self {
+            tag: pack.tag,
+            name: pack.name,
+            author: pack.author,
+            date: timestamp(pack.date.as_deref()),
+            sids: pack.beatmapsets.into_iter().map(|set| set.id).collect(),
+        }
+    }
+}
+
+fn timestamp(date: Option<&str>) -> Option<i64> {
+    OffsetDateTime::parse(date?, &Rfc3339)
+        .ok()
+        .map(OffsetDateTime::unix_timestamp)
+}
+
+impl From<V2Set> for BeatmapSet {
+    fn from(set: V2Set) -> Self {
+        let approved = match set.status.as_str() {
+            "graveyard" => RankedStatus::Graveyard,
+            "wip" => RankedStatus::Wip,
+            "pending" => RankedStatus::Pending,
+            "ranked" => RankedStatus::Ranked,
+            "approved" => RankedStatus::Approved,
+            "qualified" => RankedStatus::Qualified,
+            "loved" => RankedStatus::Loved,
+            _ => RankedStatus::Unknown(i8::MIN),
+        };
+        Self {
+            sid: set.id,
+            title: set.title,
+            title_unicode: set.title_unicode.unwrap_or_default(),
+            artist: set.artist,
+            artist_unicode: set.artist_unicode.unwrap_or_default(),
+            creator: set.creator,
+            approved,
+            modes: mode_mask(set.beatmaps.iter().map(|map| map.mode_int)),
+            play_count: set.play_count,
+            favourite_count: set.favourite_count,
+            approved_date: timestamp(set.ranked_date.as_deref()),
+            lastupdate: timestamp(set.last_updated.as_deref()).unwrap_or_default(),
+            bpm: set.bpm,
+            difficulties: set
+                .beatmaps
+                .into_iter()
+                .map(|map| Difficulty {
+                    bid: map.id,
+                    version: map.version,
+                    mode: map.mode_int,
+                    star: map.difficulty_rating,
+                    ar: map.ar,
+                    od: map.accuracy,
+                    cs: map.cs,
+                    hp: map.drain,
+                    length: map.hit_length,
+                    maxcombo: map.max_combo.unwrap_or_default(),
+                    playcount: map.playcount,
+                    passcount: map.passcount,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The query in the syntax of osu! with its filters, if `api` is a search.
+fn search_text(api: &API) -> Option<String> {
+    if api.type_ != Some(ListKind::Search) {
+        return None;
+    }
+    match &api.search {
+        Some(search) => Some(search.to_string()),
+        None => api.keyword.clone(),
+    }
+}
+
+/// The game modes of `api` as osu! ruleset ids, `None` if all are asked.
+fn mode_ids(api: &API) -> Option<Vec<u8>> {
+    let modes = api
+        .mode
+        .filter(|modes| !modes.is_all() && !modes.is_empty())?;
+    Some(
+        modes
+            .iter()
+            .map(|mode| mode.bits().trailing_zeros() as u8)
+            .collect(),
+    )
+}
+
+/// The map states of `api` as osu! status ids, `None` if all are asked.
+fn status_ids(api: &API) -> Option<Vec<i8>> {
+    let class = api
+        .class
+        .filter(|class| !class.is_all() && !class.is_empty())?;
+    let ids = [
+        (RankStatus::RANKED, &[1, 2][..]),
+        (RankStatus::QUALIFIED, &[3]),
+        (RankStatus::LOVED, &[4]),
+        (RankStatus::PENDING, &[0, -1]),
+        (RankStatus::GRAVEYARD, &[-2]),
+    ];
+    Some(
+        ids.into_iter()
+            .filter(|(flag, _)| class.contains(*flag))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect(),
+    )
+}
+
+fn unsupported<'a, T: Send + 'a>() -> BoxFuture<'a, Result<T>> {
+    async { Err(Unsupported.into()) }.boxed()
+}
+
+/// Send `req` and parse the JSON response, failing on an error status.
+async fn get<T: DeserializeOwned>(req: RequestBuilder) -> Result<T> {
+    let res = req.send().await?;
+    let url = res.url().clone();
+    tracing::debug!(%url, status = %res.status(), "response");
+    if !res.status().is_success() {
+        bail!("Request failed with status code: {}", res.status());
+    }
+    res.json()
+        .await
+        .with_context(|| format!("invalid response of {url}"))
+}
+
+/// Send a search request and convert the result to [`BeatmapList`].
+async fn search(req: RequestBuilder, offset: u32) -> Result<BeatmapList> {
+    let sets: Vec<V2Set> = get(req).await?;
+    Ok(BeatmapList {
+        status: if sets.is_empty() { -1 } else { 0 },
+        endid: offset + sets.len() as u32,
+        data: sets.into_iter().map(Into::into).collect(),
+    })
+}
+
+/// Send a request for one page of a pack list.
+async fn packs(req: RequestBuilder) -> Result<PackPage> {
+    let page: V2Packs = get(req).await?;
+    Ok(PackPage {
+        packs: page.beatmap_packs.into_iter().map(Into::into).collect(),
+        cursor: page.cursor_string,
+    })
+}
+
+/// [Mino](https://catboy.best) mirror.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Catboy;
+
+impl Catboy {
+    /// The search request answering `api`, `None` for lists it has no sort
+    /// for.
+    fn list_request(&self, client: &Client, api: &API) -> Option<RequestBuilder> {
+        let sort = match api.type_.unwrap_or(ListKind::New) {
+            ListKind::Hot => Some("play_count:desc"),
+            ListKind::New => Some("ranked_date:desc"),
+            ListKind::Search => None,
+            ListKind::Packs => return None,
+        };
+        let mut req = client
+            .get("https://catboy.best/api/v2/search")
+            .query(&[("limit", api.limit), ("offset", api.offset.unwrap_or(0))]);
+        if let Some(sort) = sort {
+            req = req.query(&[("sort", sort)]);
+        }
+        if let Some(text) = search_text(api) {
+            req = req.query(&[("q", text)]);
+        }
+        for mode in mode_ids(api).unwrap_or_default() {
+            req = req.query(&[("mode", mode)]);
+        }
+        for status in status_ids(api).unwrap_or_default() {
+            req = req.query(&[("status", status)]);
+        }
+        Some(req)
+    }
+}
+
+impl Mirror for Catboy {
+    fn name(&self) -> &str {
+        "catboy"
+    }
+
+    fn list<'a>(&'a self, client: &'a Client, api: &'a API) -> BoxFuture<'a, Result<BeatmapList>> {
+        match self.list_request(client, api) {
+            Some(req) => search(req, api.offset.unwrap_or(0)).boxed(),
+            None => unsupported(),
+        }
+    }
+
+    fn beatmap_set<'a>(
+        &'a self,
+        client: &'a Client,
+        sid: u32,
+    ) -> BoxFuture<'a, Result<BeatmapSet>> {
+        let req = client.get(format!("https://catboy.best/api/v2/s/{sid}"));
+        async move { Ok(get::<V2Set>(req).await?.into()) }.boxed()
+    }
+
+    fn packs<'a>(
+        &'a self,
+        client: &'a Client,
+        kind: PackKind,
+        cursor: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<PackPage>> {
+        let mut req = client
+            .get("https://catboy.best/api/v2/beatmaps/packs")
+            .query(&[("type", kind.api_name())]);
+        if let Some(cursor) = cursor {
+            req = req.query(&[("cursor_string", cursor)]);
+        }
+        packs(req).boxed()
+    }
+
+    fn pack<'a>(&'a self, client: &'a Client, tag: &'a str) -> BoxFuture<'a, Result<Pack>> {
+        let req = client.get(format!("https://catboy.best/api/v2/beatmaps/packs/{tag}"));
+        async move { Ok(get::<V2Pack>(req).await?.into()) }.boxed()
+    }
+
+    fn download_url(&self, sid: u32, variant: Variant) -> Option<String> {
+        match variant {
+            Variant::Full => Some(format!("https://catboy.best/d/{sid}")),
+            Variant::NoVideo => Some(format!("https://catboy.best/d/{sid}n")),
+            Variant::Mini => None,
+        }
+    }
+}
+
+/// [NeriNyan](https://nerinyan.moe) mirror.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Nerinyan;
+
+impl Nerinyan {
+    /// The search request answering `api`, `None` for lists it has no sort
+    /// for. Pages are whole multiples of `api.limit`.
+    fn list_request(&self, client: &Client, api: &API) -> Option<RequestBuilder> {
+        let sort = match api.type_.unwrap_or(ListKind::New) {
+            ListKind::Hot => Some("plays_desc"),
+            ListKind::New => Some("ranked_desc"),
+            ListKind::Search => None,
+            ListKind::Packs => return None,
+        };
+        let page = api.offset.unwrap_or(0) / api.limit.max(1);
+        let mut req = client
+            .get("https://api.nerinyan.moe/search")
+            .query(&[("ps", api.limit), ("p", page)]);
+        if let Some(sort) = sort {
+            req = req.query(&[("sort", sort)]);
+        }
+        if let Some(text) = search_text(api) {
+            req = req.query(&[("q", text)]);
+        }
+        let join = |ids: Vec<String>| ids.join(",");
+        if let Some(modes) = mode_ids(api) {
+            req = req.query(&[("m", join(modes.iter().map(u8::to_string).collect()))]);
+        }
+        if let Some(statuses) = status_ids(api) {
+            req = req.query(&[("s", join(statuses.iter().map(i8::to_string).collect()))]);
+        }
+        Some(req)
+    }
+}
+
+impl Mirror for Nerinyan {
+    fn name(&self) -> &str {
+        "nerinyan"
+    }
+
+    /// An offset within a page lists the rest of the page.
+    fn list<'a>(&'a self, client: &'a Client, api: &'a API) -> BoxFuture<'a, Result<BeatmapList>> {
+        let offset = api.offset.unwrap_or(0);
+        let start = offset / api.limit.max(1) * api.limit;
+        match self.list_request(client, api) {
+            Some(req) => async move {
+                let mut list = search(req, start).await?;
+                let skip = ((offset - start) as usize).min(list.data.len());
+                list.data.drain(..skip);
+                Ok(list)
+            }
+            .boxed(),
+            None => unsupported(),
+        }
+    }
+
+    fn download_url(&self, sid: u32, variant: Variant) -> Option<String> {
+        let query = match variant {
+            Variant::Full => "",
+            Variant::NoVideo => "?nv=1",
+            Variant::Mini => "?nv=1&nsb=1",
+        };
+        Some(format!("https://api.nerinyan.moe/d/{sid}{query}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::api::GameModes;
+
+    #[test]
+    fn test_v2_set() {
+        let sets: Vec<V2Set> =
+            serde_json::from_str(include_str!("../../../test/fixtures/osu_v2_search.json"))
+                .unwrap();
+        let set: BeatmapSet = sets.into_iter().next().unwrap().into();
+        assert_eq!(set.sid, 2071043);
+        assert_eq!(set.display_title(), "紅");
+        assert_eq!(set.approved, RankedStatus::Ranked);
+        assert_eq!(set.modes, 0b1001);
+        assert_eq!(set.approved_date, Some(1697270400));
+        assert_eq!(set.bpm, Some(218.0));
+        assert_eq!(set.difficulties.len(), 2);
+        assert_eq!(set.difficulties[1].cs, 7.0);
+        assert_eq!(set.difficulties[1].length, 158);
+    }
+
+    #[test]
+    fn test_list_request() {
+        let client = Client::new();
+        let url = |req: Option<RequestBuilder>| req.unwrap().build().unwrap().url().to_string();
+        let mut api = API::new(50);
+        api.type_ = Some(ListKind::Hot);
+        api.mode = Some(GameModes::STD | GameModes::MANIA);
+        api.class = Some(RankStatus::RANKED | RankStatus::LOVED);
+        assert_eq!(
+            url(Catboy.list_request(&client, &api)),
+            "https://catboy.best/api/v2/search?limit=50&offset=0&sort=play_count%3Adesc\
+             &mode=0&mode=3&status=1&status=2&status=4"
+        );
+        api.offset = Some(100);
+        assert_eq!(
+            url(Nerinyan.list_request(&client, &api)),
+            "https://api.nerinyan.moe/search?ps=50&p=2&sort=plays_desc&m=0%2C3&s=1%2C2%2C4"
+        );
+
+        api.type_ = Some(ListKind::Search);
+        api.keyword = Some("camellia".to_string());
+        api.mode = Some(GameModes::all());
+        api.class = None;
+        assert_eq!(
+            url(Nerinyan.list_request(&client, &api)),
+            "https://api.nerinyan.moe/search?ps=50&p=2&q=camellia"
+        );
+
+        api.type_ = Some(ListKind::Packs);
+        assert!(Catboy.list_request(&client, &api).is_none());
+        assert!(Nerinyan.list_request(&client, &api).is_none());
+    }
+
+    #[test]
+    fn test_v2_packs() {
+        let page: V2Packs =
+            serde_json::from_str(include_str!("../../../test/fixtures/osu_v2_packs.json")).unwrap();
+        assert_eq!(
+            page.cursor_string.as_deref(),
+            Some("eyJwYWNrX2lkIjoxNDk5fQ")
+        );
+        let pack: Pack = page.beatmap_packs.into_iter().next().unwrap().into();
+        assert_eq!(pack.tag, "S1500");
+        assert_eq!(pack.date, Some(1697270400));
+        assert!(pack.sids.is_empty());
+
+        let pack: Pack =
+            serde_json::from_str::<V2Pack>(include_str!("../../../test/fixtures/osu_v2_pack.json"))
+                .unwrap()
+                .into();
+        assert_eq!(pack.sids, [2071043, 1955170]);
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::{bail, Result};
+use futures::{future::BoxFuture, FutureExt};
+use reqwest::Client;
+
+use super::{Mirror, Variant};
+use crate::core::{
+    api::API,
+    beatmap::{BeatmapDetail, BeatmapDetailResponse, BeatmapList, BeatmapSet},
+};
+
+pub const API_BASE: &str = "https://api.sayobot.cn";
+pub const DOWNLOAD_BASE: &str = "https://dl.sayobot.cn";
+
+/// [Sayobot](https://osu.sayobot.cn) mirror.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sayobot {
+    api_base: String,
+    download_base: String,
+}
+
+impl Default for Sayobot {
+    fn default() -> Self {
+        Self {
+            api_base: API_BASE.to_string(),
+            download_base: DOWNLOAD_BASE.to_string(),
+        }
+    }
+}
+
+impl Sayobot {
+    /// A mirror serving sayobot compatible api and downloads under `base`.
+    pub fn with_base(base: &str) -> Self {
+        let base = base.trim_end_matches('/').to_string();
+        Self {
+            api_base: base.clone(),
+            download_base: base,
+        }
+    }
+
+    /// Request a beatmap list by sayobot `beatmaplist` command.
+    pub async fn beatmap_list(&self, client: &Client, api: &API) -> Result<BeatmapList> {
+        let url = format!("{}/?post", self.api_base);
+        tracing::debug!(%url, body = %serde_json::to_string(api)?, "request");
+        let res = client.post(&url).json(api).send().await?;
+        tracing::debug!(%url, status = %res.status(), "response");
+        if !res.status().is_success() {
+            bail!("Request failed with status code: {}", res.status());
+        }
+        let list: BeatmapList = res.json().await?;
+        match list.status {
+            0 | -1 => Ok(list),
+            code => bail!("sayobot returns error status: {code}"),
+        }
+    }
+
+    /// Request the detail of a beatmap set, including all difficulties.
+    pub async fn beatmap_info(&self, client: &Client, sid: u32) -> Result<BeatmapDetail> {
+        let url = format!("{}/v2/beatmapinfo", self.api_base);
+        tracing::debug!(%url, sid, "request");
+        let res = client
+            .get(&url)
+            .query(&[("K", sid.to_string()), ("T", "0".to_string())])
+            .send()
+            .await?;
+        tracing::debug!(%url, status = %res.status(), "response");
+        if !res.status().is_success() {
+            bail!("Request failed with status code: {}", res.status());
+        }
+        let res: BeatmapDetailResponse = res.json().await?;
+        match res.data {
+            Some(data) if res.status == 0 => Ok(data),
+            _ => bail!("beatmap set {sid} not found, status: {}", res.status),
+        }
+    }
+}
+
+impl Mirror for Sayobot {
+    fn name(&self) -> &str {
+        "sayobot"
+    }
+
+    fn list<'a>(&'a self, client: &'a Client, api: &'a API) -> BoxFuture<'a, Result<BeatmapList>> {
+        self.beatmap_list(client, api).boxed()
+    }
+
+    fn beatmap_set<'a>(
+        &'a self,
+        client: &'a Client,
+        sid: u32,
+    ) -> BoxFuture<'a, Result<BeatmapSet>> {
+        async move { Ok(self.beatmap_info(client, sid).await?.into()) }.boxed()
+    }
+
+    fn download_url(&self, sid: u32, variant: Variant) -> Option<String> {
+        let variant = match variant {
+            Variant::Full => "full",
+            Variant::NoVideo => "novideo",
+            Variant::Mini => "mini",
+        };
+        Some(format!(
+            "{}/beatmaps/download/{variant}/{sid}",
+            self.download_base
+        ))
+    }
+}
@@ -1 +1,13 @@
 pub mod api;
+pub mod beatmap;
+pub mod binary;
+pub mod collection;
+pub mod filter;
+pub mod library;
+pub mod mirror;
+pub mod osu_db;
+pub mod osu_file;
+pub mod osz;
+pub mod pack;
+pub mod query;
+pub mod search;
@@ -0,0 +1,323 @@
+//! Reader of `osu!.db`, the beatmap database of the osu! stable client.
+//!
+//! See <https://github.com/ppy/osu/wiki/Legacy-database-file-structure>.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+use super::{beatmap::RankedStatus, binary::ReadExt};
+
+/// Since this version, difficulty values are floats and star ratings are
+/// stored.
+const VERSION_FLOAT_DIFFICULTY: i32 = 20140609;
+/// Before this version, each beatmap entry starts with its size in bytes.
+const VERSION_NO_ENTRY_SIZE: i32 = 20191106;
+/// Since this version, star ratings are floats instead of doubles.
+const VERSION_FLOAT_STAR_RATING: i32 = 20250107;
+
+/// Contents of `osu!.db` needed to know the owned beatmaps.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OsuDb {
+    pub version: i32,
+    pub folder_count: i32,
+    pub player_name: String,
+    pub beatmaps: Vec<DbBeatmap>,
+}
+
+/// One difficulty in `osu!.db`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DbBeatmap {
+    pub artist: String,
+    pub artist_unicode: String,
+    pub title: String,
+    pub title_unicode: String,
+    pub creator: String,
+    pub difficulty: String,
+    pub audio_file: String,
+    pub md5: String,
+    pub osu_file: String,
+    /// 0 = unknown, 1 = unsubmitted, 2 = pending/wip/graveyard, 4 = ranked,
+    /// 5 = approved, 6 = qualified, 7 = loved.
+    pub ranked_status: u8,
+    /// 0 = std, 1 = taiko, 2 = ctb, 3 = mania.
+    pub mode: u8,
+    pub bid: i32,
+    /// Set id, or -1 if unsubmitted.
+    pub sid: i32,
+    /// Folder name in the `Songs` directory.
+    pub folder: String,
+}
+
+impl DbBeatmap {
+    pub fn status(&self) -> RankedStatus {
+        match self.ranked_status {
+            2 => RankedStatus::Pending,
+            4 => RankedStatus::Ranked,
+            5 => RankedStatus::Approved,
+            6 => RankedStatus::Qualified,
+            7 => RankedStatus::Loved,
+            _ => RankedStatus::Unknown(i8::MIN),
+        }
+    }
+}
+
+impl OsuDb {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Self::read(BufReader::new(file))
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn read(mut r: impl Read) -> Result<Self> {
+        let version = r.read_i32()?;
+        let folder_count = r.read_i32()?;
+        // account unlocked, date the account will be unlocked
+        r.read_bool()?;
+        r.read_i64()?;
+        let player_name = r.read_string()?;
+        let count = r.read_i32()?;
+        let beatmaps = (0..count)
+            .map(|i| read_beatmap(&mut r, version).with_context(|| format!("beatmap #{i}")))
+            .collect::<Result<_>>()?;
+        // user permissions follow, not needed
+        Ok(Self {
+            version,
+            folder_count,
+            player_name,
+            beatmaps,
+        })
+    }
+}
+
+/// Read a count and make sure it is not negative.
+fn read_count(r: &mut impl Read) -> io::Result<u64> {
+    let n = r.read_i32()?;
+    u64::try_from(n)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("negative count {n}")))
+}
+
+fn read_beatmap(r: &mut impl Read, version: i32) -> Result<DbBeatmap> {
+    if version < VERSION_NO_ENTRY_SIZE {
+        r.read_i32()?;
+    }
+    let mut beatmap = DbBeatmap {
+        artist: r.read_string()?,
+        artist_unicode: r.read_string()?,
+        title: r.read_string()?,
+        title_unicode: r.read_string()?,
+        creator: r.read_string()?,
+        difficulty: r.read_string()?,
+        audio_file: r.read_string()?,
+        md5: r.read_string()?,
+        osu_file: r.read_string()?,
+        ranked_status: r.read_u8()?,
+        ..Default::default()
+    };
+    // numbers of hit circles, sliders and spinners, last modification time
+    r.skip(2 * 3 + 8)?;
+    // AR, CS, HP, OD
+    r.skip(if version < VERSION_FLOAT_DIFFICULTY {
+        4
+    } else {
+        4 * 4
+    })?;
+    // slider velocity
+    r.skip(8)?;
+    if version >= VERSION_FLOAT_DIFFICULTY {
+        // star ratings of each mode: (0x08, mods, 0x0d/0x0c, star rating)
+        let pair = if version >= VERSION_FLOAT_STAR_RATING {
+            1 + 4 + 1 + 4
+        } else {
+            1 + 4 + 1 + 8
+        };
+        for _ in 0..4 {
+            let n = read_count(r)?;
+            r.skip(n * pair)?;
+        }
+    }
+    // drain time, total time, preview time
+    r.skip(4 * 3)?;
+    // timing points: (BPM, offset, uninherited)
+    let n = read_count(r)?;
+    r.skip(n * (8 + 8 + 1))?;
+    beatmap.bid = r.read_i32()?;
+    beatmap.sid = r.read_i32()?;
+    // thread id, grades of 4 modes, local offset, stack leniency
+    r.skip(4 + 4 + 2 + 4)?;
+    beatmap.mode = r.read_u8()?;
+    // source, tags, online offset
+    r.read_string()?;
+    r.read_string()?;
+    r.skip(2)?;
+    // font of the title
+    r.read_string()?;
+    // unplayed, last played, osz2
+    r.skip(1 + 8 + 1)?;
+    beatmap.folder = r.read_string()?;
+    // last checked against the osu! repository, 5 override flags
+    r.skip(8 + 5)?;
+    if version < VERSION_FLOAT_DIFFICULTY {
+        r.skip(2)?;
+    }
+    // last modification time, mania scroll speed
+    r.skip(4 + 1)?;
+    Ok(beatmap)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::core::binary::WriteExt;
+
+    /// Write a synthetic `osu!.db` of `version`.
+    pub fn write_db(version: i32, beatmaps: &[DbBeatmap]) -> Vec<u8> {
+        let mut w = vec![];
+        w.write_i32(version).unwrap();
+        w.write_i32(beatmaps.len() as i32).unwrap();
+        w.write_bool(true).unwrap();
+        w.write_i64(0).unwrap();
+        w.write_string("player").unwrap();
+        w.write_i32(beatmaps.len() as i32).unwrap();
+        for beatmap in beatmaps {
+            let entry = write_beatmap(version, beatmap);
+            if version < VERSION_NO_ENTRY_SIZE {
+                w.write_i32(entry.len() as i32).unwrap();
+            }
+            w.extend(entry);
+        }
+        w.write_i32(1).unwrap();
+        w
+    }
+
+    fn write_beatmap(version: i32, b: &DbBeatmap) -> Vec<u8> {
+        let mut w = vec![];
+        for s in [
+            &b.artist,
+            &b.artist_unicode,
+            &b.title,
+            &b.title_unicode,
+            &b.creator,
+            &b.difficulty,
+            &b.audio_file,
+            &b.md5,
+            &b.osu_file,
+        ] {
+            w.write_string(s).unwrap();
+        }
+        w.write_u8(b.ranked_status).unwrap();
+        for n in [100, 50, 1] {
+            w.write_i16(n).unwrap();
+        }
+        w.write_i64(638412345000000000).unwrap();
+        if version < VERSION_FLOAT_DIFFICULTY {
+            w.extend([9, 4, 5, 8]);
+        } else {
+            for v in [9.0, 4.0, 5.0, 8.0] {
+                w.write_f32(v).unwrap();
+            }
+        }
+        w.write_f64(1.4).unwrap();
+        if version >= VERSION_FLOAT_DIFFICULTY {
+            for mode in 0..4 {
+                w.write_i32(mode).unwrap();
+                for mods in 0..mode {
+                    w.write_u8(0x08).unwrap();
+                    w.write_i32(mods).unwrap();
+                    if version >= VERSION_FLOAT_STAR_RATING {
+                        w.write_u8(0x0c).unwrap();
+                        w.write_f32(5.5).unwrap();
+                    } else {
+                        w.write_u8(0x0d).unwrap();
+                        w.write_f64(5.5).unwrap();
+                    }
+                }
+            }
+        }
+        for n in [158, 161000, 30000] {
+            w.write_i32(n).unwrap();
+        }
+        w.write_i32(2).unwrap();
+        for _ in 0..2 {
+            w.write_f64(275.0).unwrap();
+            w.write_f64(100.0).unwrap();
+            w.write_bool(true).unwrap();
+        }
+        w.write_i32(b.bid).unwrap();
+        w.write_i32(b.sid).unwrap();
+        w.write_i32(0).unwrap();
+        w.extend([9, 9, 9, 9]);
+        w.write_i16(0).unwrap();
+        w.write_f32(0.7).unwrap();
+        w.write_u8(b.mode).unwrap();
+        w.write_string("source").unwrap();
+        w.write_string("tags").unwrap();
+        w.write_i16(0).unwrap();
+        w.write_u8(0x00).unwrap();
+        w.write_bool(true).unwrap();
+        w.write_i64(0).unwrap();
+        w.write_bool(false).unwrap();
+        w.write_string(&b.folder).unwrap();
+        w.write_i64(0).unwrap();
+        w.extend([0; 5]);
+        if version < VERSION_FLOAT_DIFFICULTY {
+            w.write_i16(0).unwrap();
+        }
+        w.write_i32(0).unwrap();
+        w.write_u8(0).unwrap();
+        w
+    }
+
+    pub fn beatmap(sid: i32, bid: i32, folder: &str) -> DbBeatmap {
+        DbBeatmap {
+            artist: "Camellia".to_string(),
+            title: "Kurenai".to_string(),
+            title_unicode: "紅".to_string(),
+            creator: "Mapper".to_string(),
+            difficulty: format!("Diff {bid}"),
+            audio_file: "audio.mp3".to_string(),
+            md5: format!("{bid:032x}"),
+            osu_file: format!("Camellia - Kurenai (Mapper) [Diff {bid}].osu"),
+            ranked_status: 4,
+            mode: 3,
+            bid,
+            sid,
+            folder: folder.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let beatmaps = [
+            beatmap(2071043, 1, "2071043 Camellia - Kurenai"),
+            beatmap(2071043, 2, "2071043 Camellia - Kurenai"),
+            beatmap(-1, 0, "unsubmitted"),
+        ];
+        for version in [20130101, 20150101, 20191106, 20250107] {
+            let db = OsuDb::read(&write_db(version, &beatmaps)[..]).unwrap();
+            assert_eq!(db.version, version);
+            assert_eq!(db.player_name, "player");
+            assert_eq!(db.beatmaps, beatmaps, "version {version}");
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let mut beatmap = beatmap(1, 1, "1");
+        assert_eq!(beatmap.status(), RankedStatus::Ranked);
+        beatmap.ranked_status = 7;
+        assert_eq!(beatmap.status(), RankedStatus::Loved);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = write_db(20191106, &[beatmap(1, 1, "1")]);
+        assert!(OsuDb::read(&data[..data.len() - 20]).is_err());
+    }
+}
@@ -0,0 +1,465 @@
+//! Parser of `.osu` beatmap files.
+//!
+//! See <https://osu.ppy.sh/wiki/en/Client/File_formats/osu_(file_format)>.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+/// A parsed `.osu` file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OsuFile {
+    /// `x` in the header `osu file format vx`.
+    pub format_version: u32,
+    pub general: General,
+    pub metadata: Metadata,
+    pub difficulty: Difficulty,
+    pub events: Events,
+    pub timing_points: Vec<TimingPoint>,
+    pub hit_objects: HitObjects,
+}
+
+/// `[General]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct General {
+    pub audio_filename: String,
+    /// 0 = std, 1 = taiko, 2 = ctb, 3 = mania.
+    pub mode: u8,
+}
+
+/// `[Metadata]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: String,
+    pub title_unicode: String,
+    pub artist: String,
+    pub artist_unicode: String,
+    pub creator: String,
+    /// Difficulty name.
+    pub version: String,
+    pub source: String,
+    pub tags: Vec<String>,
+    pub beatmap_id: Option<u32>,
+    pub beatmap_set_id: Option<u32>,
+}
+
+/// `[Difficulty]` section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difficulty {
+    pub hp_drain_rate: f32,
+    pub circle_size: f32,
+    pub overall_difficulty: f32,
+    /// Same as `overall_difficulty` in old files without it.
+    pub approach_rate: f32,
+    pub slider_multiplier: f64,
+    pub slider_tick_rate: f64,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self {
+            hp_drain_rate: 5.0,
+            circle_size: 5.0,
+            overall_difficulty: 5.0,
+            approach_rate: 5.0,
+            slider_multiplier: 1.4,
+            slider_tick_rate: 1.0,
+        }
+    }
+}
+
+/// Background and video of `[Events]` section. Storyboard is ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Events {
+    pub background: Option<String>,
+    pub video: Option<Video>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Video {
+    pub filename: String,
+    /// Start time in milliseconds.
+    pub start_time: i32,
+}
+
+/// One line of `[TimingPoints]` section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingPoint {
+    /// Start time in milliseconds.
+    pub time: f64,
+    /// Milliseconds of a beat if uninherited, otherwise a negative inverse
+    /// slider velocity multiplier in percent.
+    pub beat_length: f64,
+    /// Beats in a measure.
+    pub meter: u32,
+    /// Whether it is a red line, which sets the BPM.
+    pub uninherited: bool,
+    pub kiai: bool,
+}
+
+impl TimingPoint {
+    /// Parse a line like `time,beatLength,meter,sampleSet,sampleIndex,volume,
+    /// uninherited,effects`. Files before v6 have only the first two fields.
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split(',').map(str::trim);
+        let time = fields.next()?.parse::<f64>().ok()?;
+        let beat_length = fields.next()?.parse::<f64>().ok()?;
+        let rest: Vec<&str> = fields.collect();
+        let field = |i: usize| rest.get(i).and_then(|field| field.parse::<i64>().ok());
+        let point = Self {
+            time,
+            beat_length,
+            meter: field(0)
+                .and_then(|meter| meter.try_into().ok())
+                .unwrap_or(4),
+            uninherited: field(4).map_or(beat_length > 0.0, |value| value != 0),
+            kiai: field(5).is_some_and(|effects| effects & 1 != 0),
+        };
+        (time.is_finite() && beat_length.is_finite()).then_some(point)
+    }
+
+    /// Beats per minute of an uninherited point.
+    pub fn bpm(&self) -> Option<f64> {
+        (self.uninherited && self.beat_length > 0.0).then(|| 60000.0 / self.beat_length)
+    }
+}
+
+/// Counts and time span of `[HitObjects]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HitObjects {
+    pub circles: u32,
+    pub sliders: u32,
+    pub spinners: u32,
+    /// Mania hold notes.
+    pub holds: u32,
+    /// Start time of the first object in milliseconds.
+    pub first_time: Option<i32>,
+    /// End time of the last object in milliseconds. The end of sliders is
+    /// not calculated, so their start time is used.
+    pub last_time: Option<i32>,
+}
+
+impl HitObjects {
+    pub fn total(&self) -> u32 {
+        self.circles + self.sliders + self.spinners + self.holds
+    }
+
+    /// Add a line like `x,y,time,type,hitSound,objectParams,hitSample`.
+    fn add(&mut self, line: &str) {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let (Some(Ok(time)), Some(Ok(kind))) = (
+            fields.get(2).map(|f| f.parse::<i32>()),
+            fields.get(3).map(|f| f.parse::<u32>()),
+        ) else {
+            return;
+        };
+        let mut end = time;
+        if kind & 1 != 0 {
+            self.circles += 1;
+        } else if kind & 2 != 0 {
+            self.sliders += 1;
+        } else if kind & 8 != 0 {
+            self.spinners += 1;
+            end = fields.get(5).and_then(|f| f.parse().ok()).unwrap_or(time);
+        } else if kind & 128 != 0 {
+            self.holds += 1;
+            end = fields
+                .get(5)
+                .and_then(|f| f.split(':').next()?.parse().ok())
+                .unwrap_or(time);
+        } else {
+            return;
+        }
+        self.first_time = Some(self.first_time.map_or(time, |first| first.min(time)));
+        self.last_time = Some(self.last_time.map_or(end, |last| last.max(end.max(time))));
+    }
+}
+
+/// Parse `value` into `field`, keeping the old value if malformed.
+fn set<T: FromStr>(field: &mut T, value: &str) {
+    if let Ok(value) = value.parse() {
+        *field = value;
+    }
+}
+
+/// The file name in an event, quoted or not.
+fn event_file(field: Option<&str>) -> Option<String> {
+    let name = field?.trim().trim_matches('"');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Parse a positive id. Unsubmitted maps have 0 or -1.
+fn id(value: &str) -> Option<u32> {
+    value.parse().ok().filter(|&id| id > 0)
+}
+
+impl OsuFile {
+    /// Parse the content of a `.osu` file. Unknown sections, keys and
+    /// malformed values are ignored; only a missing header is an error.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut lines = content
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        let Some(version) = lines
+            .next()
+            .and_then(|header| header.strip_prefix("osu file format v"))
+        else {
+            bail!("not an osu file");
+        };
+        let mut file = Self {
+            format_version: version.trim().parse().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let mut section = "";
+        let mut has_ar = false;
+        for line in lines {
+            if line.starts_with("//") {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name;
+                continue;
+            }
+            match section {
+                "Events" => {
+                    let mut fields = line.split(',');
+                    match fields.next().map(str::trim) {
+                        Some("0") => {
+                            fields.next();
+                            file.events.background = event_file(fields.next());
+                        }
+                        Some("1" | "Video") => {
+                            let start_time = fields.next().and_then(|t| t.trim().parse().ok());
+                            file.events.video = event_file(fields.next()).map(|filename| Video {
+                                filename,
+                                start_time: start_time.unwrap_or_default(),
+                            });
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                "TimingPoints" => {
+                    file.timing_points.extend(TimingPoint::parse(line));
+                    continue;
+                }
+                "HitObjects" => {
+                    file.hit_objects.add(line);
+                    continue;
+                }
+                _ => {}
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match section {
+                "General" => match key.trim() {
+                    "AudioFilename" => file.general.audio_filename = value.to_string(),
+                    "Mode" => set(&mut file.general.mode, value),
+                    _ => {}
+                },
+                "Metadata" => {
+                    let metadata = &mut file.metadata;
+                    match key.trim() {
+                        "Title" => metadata.title = value.to_string(),
+                        "TitleUnicode" => metadata.title_unicode = value.to_string(),
+                        "Artist" => metadata.artist = value.to_string(),
+                        "ArtistUnicode" => metadata.artist_unicode = value.to_string(),
+                        "Creator" => metadata.creator = value.to_string(),
+                        "Version" => metadata.version = value.to_string(),
+                        "Source" => metadata.source = value.to_string(),
+                        "Tags" => {
+                            metadata.tags = value.split_whitespace().map(str::to_string).collect()
+                        }
+                        "BeatmapID" => metadata.beatmap_id = id(value),
+                        "BeatmapSetID" => metadata.beatmap_set_id = id(value),
+                        _ => {}
+                    }
+                }
+                "Difficulty" => {
+                    let difficulty = &mut file.difficulty;
+                    match key.trim() {
+                        "HPDrainRate" => set(&mut difficulty.hp_drain_rate, value),
+                        "CircleSize" => set(&mut difficulty.circle_size, value),
+                        "OverallDifficulty" => set(&mut difficulty.overall_difficulty, value),
+                        "ApproachRate" => {
+                            has_ar = true;
+                            set(&mut difficulty.approach_rate, value)
+                        }
+                        "SliderMultiplier" => set(&mut difficulty.slider_multiplier, value),
+                        "SliderTickRate" => set(&mut difficulty.slider_tick_rate, value),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !has_ar {
+            file.difficulty.approach_rate = file.difficulty.overall_difficulty;
+        }
+        Ok(file)
+    }
+
+    /// The BPM lasting the longest until the last hit object.
+    pub fn bpm(&self) -> Option<f64> {
+        let points: Vec<(f64, f64)> = self
+            .timing_points
+            .iter()
+            .filter_map(|point| Some((point.time, point.bpm()?)))
+            .collect();
+        let end = self
+            .hit_objects
+            .last_time
+            .map(f64::from)
+            .unwrap_or(f64::MIN)
+            .max(points.last()?.0);
+        let mut durations: Vec<(f64, f64)> = vec![];
+        for (i, &(time, bpm)) in points.iter().enumerate() {
+            let next = points.get(i + 1).map_or(end, |next| next.0);
+            let duration = (next - time).max(0.0);
+            match durations.iter_mut().find(|(b, _)| (b - bpm).abs() < 1e-3) {
+                Some((_, total)) => *total += duration,
+                None => durations.push((bpm, duration)),
+            }
+        }
+        durations
+            .into_iter()
+            .fold(None, |acc: Option<(f64, f64)>, (bpm, duration)| match acc {
+                Some((_, longest)) if longest >= duration => acc,
+                _ => Some((bpm, duration)),
+            })
+            .map(|(bpm, _)| bpm)
+    }
+
+    /// Milliseconds from the first to the last hit object.
+    pub fn length(&self) -> Option<u32> {
+        let (first, last) = (self.hit_objects.first_time?, self.hit_objects.last_time?);
+        u32::try_from(i64::from(last) - i64::from(first)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse() {
+        let file = OsuFile::parse(include_str!("../../test/fixtures/beatmap.osu")).unwrap();
+        assert_eq!(file.format_version, 14);
+        assert_eq!(file.general.audio_filename, "audio.mp3");
+        assert_eq!(file.general.mode, 3);
+        assert_eq!(file.metadata.title_unicode, "紅");
+        assert_eq!(file.metadata.version, "7K Hard");
+        assert_eq!(file.metadata.tags, ["camellia", "kurenai", "7k"]);
+        assert_eq!(file.metadata.beatmap_id, Some(4321));
+        assert_eq!(file.metadata.beatmap_set_id, Some(2071043));
+        assert_eq!(file.difficulty.circle_size, 7.0);
+        assert_eq!(file.difficulty.approach_rate, 5.0);
+        assert_eq!(file.events.background.as_deref(), Some("bg.jpg"));
+        assert_eq!(
+            file.events.video,
+            Some(Video {
+                filename: "video.mp4".to_string(),
+                start_time: -200
+            })
+        );
+        assert_eq!(file.timing_points.len(), 4);
+        assert!(!file.timing_points[1].uninherited);
+        assert!(file.timing_points[1].kiai);
+        assert_eq!(
+            file.hit_objects,
+            HitObjects {
+                circles: 1,
+                sliders: 1,
+                spinners: 1,
+                holds: 1,
+                first_time: Some(1000),
+                last_time: Some(30000),
+            }
+        );
+        assert_eq!(file.hit_objects.total(), 4);
+        // 120 BPM lasts 20s + 9s, 240 BPM only 1s
+        assert_eq!(file.bpm(), Some(120.0));
+        assert_eq!(file.length(), Some(29000));
+    }
+
+    #[test]
+    fn test_old_format() {
+        let content = "\u{feff}osu file format v3\n\n[General]\nAudioFilename: a.mp3\n\n\
+                       [Metadata]\nTitle:Old\nBeatmapSetID:-1\n\n\
+                       [Difficulty]\nOverallDifficulty:7\nCircleSize:oops\n\n\
+                       [Events]\n0,0,bg.png\n\n\
+                       [TimingPoints]\n100,400\n\n\
+                       [HitObjects]\n64,64,100,1,0\n64,64,900,5,0\n";
+        let file = OsuFile::parse(content).unwrap();
+        assert_eq!(file.format_version, 3);
+        assert_eq!(file.metadata.title, "Old");
+        assert_eq!(file.metadata.beatmap_set_id, None);
+        assert_eq!(file.difficulty.approach_rate, 7.0);
+        assert_eq!(file.difficulty.circle_size, 5.0);
+        assert_eq!(file.events.background.as_deref(), Some("bg.png"));
+        assert!(file.timing_points[0].uninherited);
+        assert_eq!(file.timing_points[0].meter, 4);
+        assert_eq!(file.bpm(), Some(150.0));
+        assert_eq!(file.hit_objects.circles, 2);
+        assert_eq!(file.length(), Some(800));
+
+        assert!(OsuFile::parse("<!DOCTYPE html>").is_err());
+        assert!(OsuFile::parse("").is_err());
+    }
+
+    /// A line likely to hit the parser of some section.
+    fn line() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "\\[(General|Metadata|Difficulty|Events|TimingPoints|HitObjects)\\]",
+            "[A-Za-z]{1,16}:[ -~]{0,16}",
+            "(-?[0-9]{1,10}(\\.[0-9]{0,3})?,){0,8}-?[0-9]{0,10}",
+            "(0|1|Video),[0-9-]{0,6},\"?[ -~]{0,8}\"?",
+            any::<String>(),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_never_panics(content in any::<String>()) {
+            let _ = OsuFile::parse(&content);
+        }
+
+        #[test]
+        fn test_never_panics_on_sections(
+            version in 0u32..20,
+            lines in prop::collection::vec(line(), 0..40),
+        ) {
+            let content = format!("osu file format v{version}\n{}", lines.join("\n"));
+            let file = OsuFile::parse(&content).unwrap();
+            prop_assert_eq!(file.format_version, version);
+            let _ = (file.bpm(), file.length());
+        }
+
+        #[test]
+        fn test_metadata_round_trip(
+            title in "[^\\s:\\[][^\\r\\n]{0,30}[^\\s]",
+            sid in 1u32..,
+            cs in 0u32..=100,
+            bpm in 1u32..=1000,
+        ) {
+            let cs = cs as f32 / 10.0;
+            let content = format!(
+                "osu file format v14\n[Metadata]\nTitle:{title}\nBeatmapSetID:{sid}\n\
+                 [Difficulty]\nCircleSize:{cs}\n\
+                 [TimingPoints]\n0,{},4,1,0,100,1,0\n",
+                60000.0 / bpm as f64
+            );
+            let file = OsuFile::parse(&content).unwrap();
+            prop_assert_eq!(&file.metadata.title, title.trim());
+            prop_assert_eq!(file.metadata.beatmap_set_id, Some(sid));
+            prop_assert_eq!(file.difficulty.circle_size, cs);
+            prop_assert!((file.bpm().unwrap() - bpm as f64).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Validation of downloaded `.osz` archives.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use anyhow::Result;
+
+use super::osu_file::{Metadata, OsuFile};
+
+/// The file is not a usable `.osz`, e.g. a truncated archive or an error
+/// page. Downloading it again from the same url does not help.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidOsz(pub String);
+
+impl fmt::Display for InvalidOsz {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid osz: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidOsz {}
+
+/// Difficulties in a valid `.osz`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OszInfo {
+    /// Never empty.
+    pub beatmaps: Vec<OsuFile>,
+}
+
+impl OszInfo {
+    /// Open `path` as a zip archive and parse every `.osu` file in it.
+    ///
+    /// Fails with [`InvalidOsz`] if the archive is broken or has no `.osu`
+    /// file.
+    pub fn read(path: &Path) -> Result<Self> {
+        let invalid = |e: &dyn fmt::Display| InvalidOsz(e.to_string());
+        let file = BufReader::new(File::open(path)?);
+        let mut zip = zip::ZipArchive::new(file).map_err(|e| invalid(&e))?;
+        let mut beatmaps = vec![];
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i).map_err(|e| invalid(&e))?;
+            if !entry.name().to_lowercase().ends_with(".osu") {
+                continue;
+            }
+            let name = entry.name().to_string();
+            let mut content = vec![];
+            entry
+                .read_to_end(&mut content)
+                .map_err(|e| invalid(&format!("{name}: {e}")))?;
+            let beatmap = OsuFile::parse(&String::from_utf8_lossy(&content))
+                .map_err(|e| invalid(&format!("{name}: {e}")))?;
+            beatmaps.push(beatmap);
+        }
+        if beatmaps.is_empty() {
+            return Err(InvalidOsz("no .osu file".to_string()).into());
+        }
+        Ok(Self { beatmaps })
+    }
+
+    /// Metadata of the first difficulty.
+    pub fn metadata(&self) -> &Metadata {
+        &self.beatmaps[0].metadata
+    }
+
+    /// The set id written in the difficulties, if any.
+    pub fn sid(&self) -> Option<u32> {
+        self.beatmaps
+            .iter()
+            .find_map(|beatmap| beatmap.metadata.beatmap_set_id)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    /// A small `.osz` of set `sid` with two difficulties and some audio.
+    pub fn osz(sid: u32) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for version in ["Easy", "Hard"] {
+            zip.start_file(format!("Artist - Title (Mapper) [{version}].osu"), options)
+                .unwrap();
+            write!(
+                zip,
+                "osu file format v14\n\n[Metadata]\nTitle:Title\nArtist:Artist\n\
+                 Creator:Mapper\nVersion:{version}\nBeatmapSetID:{sid}\n"
+            )
+            .unwrap();
+        }
+        zip.start_file("audio.mp3", options).unwrap();
+        zip.write_all(&(0..=255).cycle().take(4096).collect::<Vec<u8>>())
+            .unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("1.osz");
+        std::fs::write(&path, osz(1)).unwrap();
+        let info = OszInfo::read(&path).unwrap();
+        assert_eq!(info.beatmaps.len(), 2);
+        assert_eq!(info.sid(), Some(1));
+        assert_eq!(info.metadata().title, "Title");
+        assert_eq!(info.beatmaps[1].metadata.version, "Hard");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("1.osz");
+        let data = osz(1);
+        for content in [
+            b"<html>503 Service Unavailable</html>".to_vec(),
+            data[..data.len() / 2].to_vec(),
+        ] {
+            std::fs::write(&path, content).unwrap();
+            assert!(OszInfo::read(&path).unwrap_err().is::<InvalidOsz>());
+        }
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        zip.start_file("audio.mp3", Default::default()).unwrap();
+        std::fs::write(&path, zip.finish().unwrap().into_inner()).unwrap();
+        assert!(OszInfo::read(&path).unwrap_err().is::<InvalidOsz>());
+    }
+}
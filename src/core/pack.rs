@@ -0,0 +1,174 @@
+//! Official beatmap packs of osu!.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context, Error, Result};
+use clap::ValueEnum;
+
+/// Kinds of official beatmap packs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum PackKind {
+    #[default]
+    Standard,
+    Theme,
+    Artist,
+    Spotlight,
+    Loved,
+}
+
+impl PackKind {
+    /// The pack `type` of the osu! api.
+    pub fn api_name(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Theme => "theme",
+            Self::Artist => "artist",
+            Self::Spotlight => "chart",
+            Self::Loved => "loved",
+        }
+    }
+
+    /// The letter before the number in tags of packs of this kind, like `S`
+    /// of `S1000`.
+    pub fn prefix(self) -> char {
+        match self {
+            Self::Standard => 'S',
+            Self::Theme => 'T',
+            Self::Artist => 'A',
+            Self::Spotlight => 'R',
+            Self::Loved => 'L',
+        }
+    }
+
+    pub fn from_prefix(prefix: char) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .copied()
+            .find(|kind| kind.prefix() == prefix.to_ascii_uppercase())
+    }
+}
+
+/// A beatmap pack.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pack {
+    /// Like `S1000`.
+    pub tag: String,
+    pub name: String,
+    pub author: String,
+    /// Unix timestamp of the release.
+    pub date: Option<i64>,
+    /// Ids of the sets in the pack, empty in pack lists.
+    pub sids: Vec<u32>,
+}
+
+/// One page of a pack list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackPage {
+    pub packs: Vec<Pack>,
+    /// Where the next page starts, `None` on the last page.
+    pub cursor: Option<String>,
+}
+
+/// Most packs in one [`PackRange`], as each pack is one request.
+const MAX_RANGE: u32 = 500;
+
+/// Packs of one kind with consecutive numbers, written like `S1000-S1100`,
+/// `S1000-1100` or `S1000`, at most [`MAX_RANGE`] of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackRange {
+    pub kind: PackKind,
+    pub first: u32,
+    pub last: u32,
+}
+
+impl PackRange {
+    /// Tags of the packs in the range.
+    pub fn tags(&self) -> impl Iterator<Item = String> + '_ {
+        (self.first..=self.last).map(|n| format!("{}{n}", self.kind.prefix()))
+    }
+}
+
+/// Split a tag like `S1000` into its kind and number.
+fn parse_tag(tag: &str) -> Result<(PackKind, u32)> {
+    let mut chars = tag.chars();
+    let prefix = chars.next().context("empty pack tag")?;
+    let Some(kind) = PackKind::from_prefix(prefix) else {
+        let prefixes: String = PackKind::value_variants()
+            .iter()
+            .map(|kind| kind.prefix())
+            .collect();
+        bail!("unknown pack `{tag}`, expected a tag starting with one of {prefixes}");
+    };
+    let number = chars
+        .as_str()
+        .parse()
+        .with_context(|| format!("invalid pack number in `{tag}`"))?;
+    Ok((kind, number))
+}
+
+impl FromStr for PackRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (first, last) = s.trim().split_once('-').unwrap_or((s.trim(), ""));
+        let (kind, first) = parse_tag(first.trim())?;
+        let last = match last.trim() {
+            "" => first,
+            last if last.starts_with(|c: char| c.is_ascii_digit()) => last.parse()?,
+            last => match parse_tag(last)? {
+                (last_kind, last) if last_kind == kind => last,
+                _ => bail!("packs of `{s}` are not of the same kind"),
+            },
+        };
+        if last < first {
+            bail!("pack range `{s}` ends before it starts");
+        }
+        if last - first >= MAX_RANGE {
+            bail!("pack range `{s}` has more than {MAX_RANGE} packs, split it");
+        }
+        Ok(Self { kind, first, last })
+    }
+}
+
+impl fmt::Display for PackRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = self.kind.prefix();
+        write!(f, "{prefix}{}", self.first)?;
+        if self.last != self.first {
+            write!(f, "-{prefix}{}", self.last)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_range() {
+        let range: PackRange = "S1000-S1002".parse().unwrap();
+        assert_eq!(range.kind, PackKind::Standard);
+        assert_eq!(
+            range.tags().collect::<Vec<_>>(),
+            ["S1000", "S1001", "S1002"]
+        );
+        assert_eq!(range.to_string(), "S1000-S1002");
+        assert_eq!("s1000-1002".parse::<PackRange>().unwrap(), range);
+
+        let range: PackRange = "L12".parse().unwrap();
+        assert_eq!(
+            (range.kind, range.first, range.last),
+            (PackKind::Loved, 12, 12)
+        );
+        assert_eq!(range.to_string(), "L12");
+
+        assert!("S1-S500".parse::<PackRange>().is_ok());
+        let err = "S1-S4000000000".parse::<PackRange>().unwrap_err();
+        assert!(err.to_string().contains("more than 500 packs"), "{err}");
+
+        for s in ["", "X1", "S", "S1000-T1001", "S10-S9", "S1-x", "S1-501"] {
+            assert!(s.parse::<PackRange>().is_err(), "{s}");
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Beatmap list queries, shared by the TUI and the command line.
+
+use clap::{Args, ValueEnum};
+use time::{Date, OffsetDateTime};
+
+use super::{
+    api::{names, parse_date, GameModes, ListKind, RankStatus, API},
+    filter::Filters,
+    search::SearchQuery,
+};
+
+/// Number of sets requested when not given.
+pub const DEFAULT_LIMIT: u32 = 50;
+
+/// Which list to request, in the order of the TUI list. Unlike
+/// [`ListKind`], which mirrors are sent, it has `Date`,
+/// which pages through `New` on our side, and no `Packs`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListMode {
+    /// Every set since a date.
+    Date,
+    Hot,
+    #[default]
+    New,
+    Search,
+}
+
+/// Everything the main page of the TUI can express.
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct ListQuery {
+    /// Defaults to `date` with `--since`, `search` with `--query`, otherwise
+    /// `new`.
+    #[arg(value_enum, help = t!("cli.list"))]
+    pub list: Option<ListMode>,
+    #[arg(long, default_value_t = DEFAULT_LIMIT, help = t!("cli.limit"))]
+    pub limit: u32,
+    #[arg(long, help = t!("cli.offset"))]
+    pub offset: Option<u32>,
+    /// Page through the newest sets until this date, today if not given.
+    #[arg(long, value_parser = parse_date, help = t!("cli.since"))]
+    pub since: Option<Date>,
+    /// Words and filters like `stars>5 mode=mania keys=7`.
+    #[arg(long, value_parser = str::parse::<SearchQuery>, help = t!("cli.query"))]
+    pub query: Option<SearchQuery>,
+    /// The default of the config if not given.
+    #[arg(long, value_parser = names::parse::<GameModes>, help = t!("cli.mode"))]
+    pub mode: Option<GameModes>,
+    /// The default of the config if not given.
+    #[arg(long, value_parser = names::parse::<RankStatus>, help = t!("cli.status"))]
+    pub status: Option<RankStatus>,
+    #[command(flatten)]
+    pub filters: Filters,
+}
+
+impl Default for ListQuery {
+    fn default() -> Self {
+        Self {
+            list: None,
+            limit: DEFAULT_LIMIT,
+            offset: None,
+            since: None,
+            query: None,
+            mode: None,
+            status: None,
+            filters: Filters::default(),
+        }
+    }
+}
+
+impl ListQuery {
+    pub fn list_mode(&self) -> ListMode {
+        match (self.list, &self.since, &self.query) {
+            (Some(list), _, _) => list,
+            (None, Some(_), _) => ListMode::Date,
+            (None, None, Some(_)) => ListMode::Search,
+            (None, None, None) => ListMode::New,
+        }
+    }
+
+    /// The request sent to mirrors.
+    pub fn to_api(&self) -> API {
+        let mut api = API::new(self.limit);
+        api.offset = self.offset;
+        match self.list_mode() {
+            ListMode::Date => {
+                api.type_ = Some(ListKind::New);
+                api.since = Some(
+                    self.since
+                        .unwrap_or_else(|| OffsetDateTime::now_utc().date()),
+                );
+            }
+            ListMode::Hot => api.type_ = Some(ListKind::Hot),
+            ListMode::New => api.type_ = Some(ListKind::New),
+            ListMode::Search => {
+                let search = self.query.clone().unwrap_or_default();
+                api.type_ = Some(ListKind::Search);
+                api.keyword = Some(search.keyword());
+                api.search = Some(search);
+            }
+        }
+        let mut search = api.search.take().unwrap_or_default();
+        search.filters.extend(self.filters.to_filters());
+        api.search = (!search.filters.is_empty()).then_some(search);
+        // filters of modes and states narrow the ones asked by the flags
+        let search = api.search.as_ref();
+        api.mode = match (self.mode, search.and_then(SearchQuery::modes)) {
+            (Some(mode), Some(allowed)) => Some(mode & allowed),
+            (mode, allowed) => mode.or(allowed),
+        };
+        api.class = match (self.status, search.and_then(SearchQuery::statuses)) {
+            (Some(status), Some(allowed)) => Some(status & allowed),
+            (status, allowed) => status.or(allowed),
+        };
+        api
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        query: ListQuery,
+    }
+
+    fn parse(args: &[&str]) -> ListQuery {
+        Cli::parse_from(["test"].iter().chain(args)).query
+    }
+
+    #[test]
+    fn test_to_api() {
+        let api = parse(&["hot", "--limit", "20", "--mode", "std,mania"]).to_api();
+        assert_eq!((api.type_, api.limit), (Some(ListKind::Hot), 20));
+        assert_eq!(api.mode, Some(GameModes::STD | GameModes::MANIA));
+        assert_eq!(api.class, None);
+
+        let api = parse(&["--since", "2024-01-31", "--status", "ranked,loved"]).to_api();
+        assert_eq!(api.type_, Some(ListKind::New));
+        assert_eq!(api.since, Some(parse_date("2024-01-31").unwrap()));
+        assert_eq!(api.class, Some(RankStatus::RANKED | RankStatus::LOVED));
+
+        let api = parse(&["--query", "freedom dive", "--offset", "50"]).to_api();
+        assert_eq!(api.type_, Some(ListKind::Search));
+        assert_eq!(api.keyword.as_deref(), Some("freedom dive"));
+        assert_eq!(api.search, None);
+        assert_eq!((api.limit, api.offset), (DEFAULT_LIMIT, Some(50)));
+
+        let api = parse(&["--query", "dive stars>5 mode=mania", "--mode", "std,mania"]).to_api();
+        assert_eq!(api.keyword.as_deref(), Some("dive"));
+        assert_eq!(api.mode, Some(GameModes::MANIA));
+        assert_eq!(api.search.unwrap().filters.len(), 2);
+        assert!(Cli::try_parse_from(["test", "--query", "stars>x"]).is_err());
+
+        let api = parse(&["hot", "--stars", "5..", "--length", "..2:00"]).to_api();
+        let filters: Vec<_> = api
+            .search
+            .unwrap()
+            .filters
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(filters, ["stars>=5", "length<=120"]);
+
+        let api = parse(&["date"]).to_api();
+        // today, which may change while the test runs
+        assert!(api.since.is_some());
+        assert_eq!(parse(&[]).to_api().type_, Some(ListKind::New));
+    }
+}
@@ -0,0 +1,570 @@
+//! Search queries in the syntax of osu!, like
+//! `camellia stars>5 bpm>=180 creator="some one" mode=mania keys=7`.
+
+use std::{fmt, str::FromStr};
+
+use super::{
+    api::{GameModes, RankStatus},
+    beatmap::{BeatmapSet, Difficulty},
+};
+
+/// A parsed search query: free text words and filters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub words: Vec<String>,
+    pub filters: Vec<Filter>,
+}
+
+/// One `key<op>value` of a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub field: Field,
+    pub op: Op,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Stars,
+    Bpm,
+    /// Drain length in seconds.
+    Length,
+    Ar,
+    Od,
+    Cs,
+    Hp,
+    /// Key count of mania difficulties.
+    Keys,
+    Creator,
+    Status,
+    Mode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f32),
+    Text(String),
+    Status(RankStatus),
+    Mode(GameModes),
+}
+
+/// A syntax error in a search query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based column of the character in error.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+const FIELDS: [(&str, Field); 11] = [
+    ("stars", Field::Stars),
+    ("bpm", Field::Bpm),
+    ("length", Field::Length),
+    ("ar", Field::Ar),
+    ("od", Field::Od),
+    ("cs", Field::Cs),
+    ("hp", Field::Hp),
+    ("keys", Field::Keys),
+    ("creator", Field::Creator),
+    ("status", Field::Status),
+    ("mode", Field::Mode),
+];
+
+/// Status names of osu!, with the flag each falls into.
+const STATUSES: [(&str, RankStatus); 7] = [
+    ("ranked", RankStatus::RANKED),
+    ("approved", RankStatus::RANKED),
+    ("qualified", RankStatus::QUALIFIED),
+    ("loved", RankStatus::LOVED),
+    ("pending", RankStatus::PENDING),
+    ("wip", RankStatus::PENDING),
+    ("graveyard", RankStatus::GRAVEYARD),
+];
+
+/// Mode names of osu! first, then the names of this app.
+const MODES: [(&str, GameModes); 7] = [
+    ("osu", GameModes::STD),
+    ("taiko", GameModes::TAIKO),
+    ("fruits", GameModes::CTB),
+    ("mania", GameModes::MANIA),
+    ("std", GameModes::STD),
+    ("ctb", GameModes::CTB),
+    ("catch", GameModes::CTB),
+];
+
+impl Field {
+    pub fn name(self) -> &'static str {
+        FIELDS.iter().find(|(_, field)| *field == self).unwrap().0
+    }
+
+    /// Whether the field is of each difficulty rather than of the set.
+    fn of_difficulty(self) -> bool {
+        !matches!(self, Self::Bpm | Self::Creator | Self::Status)
+    }
+}
+
+impl Op {
+    /// Operators by their text, longer ones first.
+    const ALL: [(&'static str, Op); 8] = [
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        ("!=", Op::Ne),
+        ("==", Op::Eq),
+        ("=", Op::Eq),
+        (":", Op::Eq),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+    ];
+
+    fn compare(self, left: f32, right: f32) -> bool {
+        match self {
+            // values like stars are rounded for display
+            Self::Eq => (left - right).abs() < 0.005,
+            Self::Ne => (left - right).abs() >= 0.005,
+            Self::Lt => left < right,
+            Self::Le => left <= right,
+            Self::Gt => left > right,
+            Self::Ge => left >= right,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+/// Characters of `s` with their byte offsets and 1-based columns.
+struct Chars<'a> {
+    s: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl<'a> Chars<'a> {
+    fn column(&self, offset: usize) -> usize {
+        self.s[..offset].chars().count() + 1
+    }
+
+    /// The next token and its offset: a run of characters up to a whitespace
+    /// outside double quotes.
+    fn token(&mut self) -> Result<Option<(usize, &'a str)>, ParseError> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let Some(&(start, _)) = self.chars.peek() else {
+            return Ok(None);
+        };
+        let mut quote = None;
+        let mut end = self.s.len();
+        while let Some(&(offset, c)) = self.chars.peek() {
+            if c.is_whitespace() && quote.is_none() {
+                end = offset;
+                break;
+            }
+            if c == '"' {
+                quote = if quote.is_some() { None } else { Some(offset) };
+            }
+            self.chars.next();
+        }
+        if let Some(offset) = quote {
+            return Err(ParseError {
+                column: self.column(offset),
+                message: "unclosed quote".to_string(),
+            });
+        }
+        Ok(Some((start, &self.s[start..end])))
+    }
+}
+
+fn unquote(s: &str) -> String {
+    s.replace('"', "")
+}
+
+/// Split `token` into a key, an operator and the offset of the value if it is
+/// like `key<op>value`.
+fn split_filter(token: &str) -> Option<(&str, Op, usize)> {
+    let key_end = token.find(|c: char| !c.is_ascii_alphabetic())?;
+    let key = &token[..key_end];
+    let (text, op) = Op::ALL
+        .iter()
+        .find(|(text, _)| token[key_end..].starts_with(text))?;
+    (!key.is_empty()).then_some((key, *op, key_end + text.len()))
+}
+
+/// Parse the value of `field`, erroring at `column`.
+fn parse_value(field: Field, op: Op, value: &str, column: usize) -> Result<Value, ParseError> {
+    let error = |message: String| ParseError { column, message };
+    if value.is_empty() {
+        return Err(error(format!("missing value of `{}`", field.name())));
+    }
+    let value = match field {
+        Field::Creator => Value::Text(unquote(value)),
+        Field::Status => {
+            let status = STATUSES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(value))
+                .ok_or_else(|| {
+                    let names: Vec<_> = STATUSES.iter().map(|(name, _)| *name).collect();
+                    error(format!(
+                        "unknown status `{value}`, expected {}",
+                        names.join(", ")
+                    ))
+                })?;
+            Value::Status(status.1)
+        }
+        Field::Mode => {
+            let mode = MODES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(value))
+                .ok_or_else(|| {
+                    let names: Vec<_> = MODES[..4].iter().map(|(name, _)| *name).collect();
+                    error(format!(
+                        "unknown mode `{value}`, expected {}",
+                        names.join(", ")
+                    ))
+                })?;
+            Value::Mode(mode.1)
+        }
+        Field::Length => Value::Number(parse_length(value).ok_or_else(|| {
+            error(format!(
+                "invalid length `{value}`, expected seconds or m:ss"
+            ))
+        })?),
+        _ => Value::Number(
+            value
+                .parse()
+                .map_err(|_| error(format!("invalid number `{value}`")))?,
+        ),
+    };
+    if !matches!(value, Value::Number(_)) && !matches!(op, Op::Eq | Op::Ne) {
+        return Err(error(format!(
+            "`{}` only takes `=` or `!=`, not `{op}`",
+            field.name()
+        )));
+    }
+    Ok(value)
+}
+
+/// Seconds like `90`, `90s`, `1m30s` or `1:30`.
+pub fn parse_length(s: &str) -> Option<f32> {
+    if let Some((minutes, seconds)) = s.split_once(':') {
+        return Some(minutes.parse::<f32>().ok()? * 60.0 + seconds.parse::<f32>().ok()?);
+    }
+    let (minutes, seconds) = match s.split_once('m') {
+        Some((minutes, seconds)) => (minutes.parse::<f32>().ok()?, seconds),
+        None => (0.0, s),
+    };
+    let seconds = match seconds.trim_end_matches('s') {
+        "" => 0.0,
+        seconds => seconds.parse().ok()?,
+    };
+    Some(minutes * 60.0 + seconds)
+}
+
+impl FromStr for SearchQuery {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let mut chars = Chars {
+            s,
+            chars: s.char_indices().peekable(),
+        };
+        let mut query = Self::default();
+        while let Some((start, token)) = chars.token()? {
+            let Some((key, op, value_start)) = split_filter(token) else {
+                query.words.push(unquote(token));
+                continue;
+            };
+            let Some(&(_, field)) = FIELDS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            else {
+                // words like `re:zero` are text, typos like `stas>5` are not
+                if op == Op::Eq && token[key.len()..].starts_with(':') {
+                    query.words.push(unquote(token));
+                    continue;
+                }
+                let names: Vec<_> = FIELDS.iter().map(|(name, _)| *name).collect();
+                return Err(ParseError {
+                    column: chars.column(start),
+                    message: format!("unknown filter `{key}`, expected {}", names.join(", ")),
+                });
+            };
+            let column = chars.column(start + value_start);
+            let value = parse_value(field, op, &token[value_start..], column)?;
+            query.filters.push(Filter { field, op, value });
+        }
+        Ok(query)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.field.name(), self.op)?;
+        match &self.value {
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Text(s) if s.contains(char::is_whitespace) => write!(f, "\"{s}\""),
+            Value::Text(s) => f.write_str(s),
+            Value::Status(status) => {
+                let name = STATUSES.iter().find(|(_, s)| s == status).unwrap().0;
+                f.write_str(name)
+            }
+            Value::Mode(mode) => {
+                let name = MODES.iter().find(|(_, m)| m == mode).unwrap().0;
+                f.write_str(name)
+            }
+        }
+    }
+}
+
+/// The query in the syntax of osu!, as sent to mirrors speaking it.
+impl fmt::Display for SearchQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words = self.words.iter().map(|word| {
+            if word.contains(char::is_whitespace) {
+                format!("\"{word}\"")
+            } else {
+                word.clone()
+            }
+        });
+        let filters = self.filters.iter().map(ToString::to_string);
+        let parts: Vec<_> = words.chain(filters).collect();
+        f.write_str(&parts.join(" "))
+    }
+}
+
+impl Filter {
+    /// Whether `set` passes a filter of the set. Unknown values pass.
+    fn matches_set(&self, set: &BeatmapSet) -> bool {
+        match (&self.field, &self.value) {
+            (Field::Bpm, Value::Number(bpm)) => set.bpm.is_none_or(|b| self.op.compare(b, *bpm)),
+            (Field::Creator, Value::Text(creator)) => {
+                set.creator.eq_ignore_ascii_case(creator) == (self.op == Op::Eq)
+            }
+            (Field::Status, Value::Status(status)) => {
+                set.approved.rank_status().intersects(*status) == (self.op == Op::Eq)
+            }
+            // without difficulties only the modes of the set are known
+            (Field::Mode, Value::Mode(mode)) if set.difficulties.is_empty() => {
+                let modes = GameModes::from_bits_truncate(set.modes.into());
+                match self.op {
+                    Op::Ne => modes.difference(*mode) != GameModes::empty(),
+                    _ => modes.intersects(*mode),
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether `difficulty` passes a filter of difficulties.
+    fn matches_difficulty(&self, difficulty: &Difficulty) -> bool {
+        let number = match (&self.field, &self.value) {
+            (Field::Mode, Value::Mode(mode)) => {
+                let mode = mode.contains(GameModes::from_bits_truncate(1 << difficulty.mode));
+                return mode == (self.op == Op::Eq);
+            }
+            (Field::Keys, Value::Number(keys)) => {
+                return difficulty.mode == 3 && self.op.compare(difficulty.cs, *keys);
+            }
+            (_, Value::Number(number)) => *number,
+            _ => return true,
+        };
+        let value = match self.field {
+            Field::Stars => difficulty.star,
+            Field::Length => difficulty.length as f32,
+            Field::Ar => difficulty.ar,
+            Field::Od => difficulty.od,
+            Field::Cs => difficulty.cs,
+            Field::Hp => difficulty.hp,
+            _ => return true,
+        };
+        self.op.compare(value, number)
+    }
+}
+
+impl SearchQuery {
+    /// Free text of the query.
+    pub fn keyword(&self) -> String {
+        self.words.join(" ")
+    }
+
+    /// The game modes allowed by the `mode` filters, `None` without any.
+    pub fn modes(&self) -> Option<GameModes> {
+        self.allowed(Field::Mode, |value| match value {
+            Value::Mode(mode) => *mode,
+            _ => GameModes::all(),
+        })
+    }
+
+    /// The map states allowed by the `status` filters, `None` without any.
+    pub fn statuses(&self) -> Option<RankStatus> {
+        self.allowed(Field::Status, |value| match value {
+            Value::Status(status) => *status,
+            _ => RankStatus::all(),
+        })
+    }
+
+    fn allowed<F: bitflags::Flags + Copy>(
+        &self,
+        field: Field,
+        flags: impl Fn(&Value) -> F,
+    ) -> Option<F> {
+        self.filters
+            .iter()
+            .filter(|filter| filter.field == field)
+            .map(|filter| match filter.op {
+                Op::Ne => flags(&filter.value).complement(),
+                _ => flags(&filter.value),
+            })
+            .reduce(|a, b| a.intersection(b))
+    }
+
+    /// Whether `set` passes all filters. A set passes filters of difficulties
+    /// if one of its difficulties passes them all, or if its difficulties
+    /// are unknown.
+    pub fn matches(&self, set: &BeatmapSet) -> bool {
+        let (of_difficulty, of_set): (Vec<_>, Vec<_>) = self
+            .filters
+            .iter()
+            .partition(|filter| filter.field.of_difficulty());
+        of_set.iter().all(|filter| filter.matches_set(set))
+            && (set.difficulties.is_empty() && of_difficulty.iter().all(|f| f.matches_set(set))
+                || set.difficulties.iter().any(|difficulty| {
+                    of_difficulty
+                        .iter()
+                        .all(|filter| filter.matches_difficulty(difficulty))
+                }))
+    }
+
+    /// Whether checking the query needs the difficulties of sets, which some
+    /// lists leave out.
+    pub fn needs_difficulties(&self) -> bool {
+        self.filters
+            .iter()
+            .any(|filter| filter.field.of_difficulty() && filter.field != Field::Mode)
+    }
+
+    /// Whether `set` passes all filters like [`SearchQuery::matches`], dropping
+    /// the difficulties not passing them.
+    pub fn retain(&self, set: &mut BeatmapSet) -> bool {
+        if !self.matches(set) {
+            return false;
+        }
+        set.difficulties.retain(|difficulty| {
+            self.filters
+                .iter()
+                .filter(|filter| filter.field.of_difficulty())
+                .all(|filter| filter.matches_difficulty(difficulty))
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::beatmap::{BeatmapList, RankedStatus};
+
+    fn parse(s: &str) -> SearchQuery {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = parse(
+            r#"freedom dive stars>5 bpm>=180 length<2:00 creator="some one" status=ranked mode=mania keys=7"#,
+        );
+        assert_eq!(query.keyword(), "freedom dive");
+        assert_eq!(query.filters.len(), 7);
+        assert_eq!(
+            query.filters[2],
+            Filter {
+                field: Field::Length,
+                op: Op::Lt,
+                value: Value::Number(120.0),
+            }
+        );
+        assert_eq!(query.filters[3].value, Value::Text("some one".to_string()));
+        assert_eq!(query.modes(), Some(GameModes::MANIA));
+        assert_eq!(query.statuses(), Some(RankStatus::RANKED));
+        assert_eq!(
+            query.to_string(),
+            r#"freedom dive stars>5 bpm>=180 length<120 creator="some one" status=ranked mode=mania keys=7"#
+        );
+        assert_eq!(parse("re:zero AR:9").keyword(), "re:zero");
+        assert_eq!(
+            parse("mode!=std").modes(),
+            Some(GameModes::all() - GameModes::STD)
+        );
+        assert_eq!(parse("").to_string(), "");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = |s: &str| s.parse::<SearchQuery>().unwrap_err();
+        assert_eq!(error("a stas>5").column, 3);
+        assert_eq!(error("stars>five").column, 7);
+        assert_eq!(error("mode=std bpm>").column, 14);
+        assert_eq!(error("status>ranked").column, 8);
+        assert_eq!(error("creator=\"a b").column, 9);
+        assert_eq!(
+            error("mode=osu!").to_string(),
+            "column 6: unknown mode `osu!`, expected osu, taiko, fruits, mania"
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let list: BeatmapList =
+            serde_json::from_str(include_str!("../../test/fixtures/beatmaplist.json")).unwrap();
+        let mut set = list.data[0].clone();
+        assert!(parse("creator=shurelia status=ranked mode=mania stars>5").matches(&set));
+        assert!(!parse("creator!=shurelia").matches(&set));
+        assert!(!parse("mode=taiko").matches(&set));
+
+        set.approved = RankedStatus::Ranked;
+        set.modes = 0b1001;
+        let difficulty = |mode, star, cs| Difficulty {
+            mode,
+            star,
+            cs,
+            ..Default::default()
+        };
+        set.difficulties = vec![difficulty(0, 6.5, 4.0), difficulty(3, 4.0, 7.0)];
+        assert!(parse("stars>5").matches(&set));
+        assert!(parse("keys=7").matches(&set));
+        assert!(!parse("keys=7 stars>5").matches(&set));
+        assert!(parse("mode=mania stars<5").matches(&set));
+        assert!(!parse("mode=osu keys=4").matches(&set));
+
+        assert!(parse("keys=7").needs_difficulties());
+        assert!(!parse("mode=mania creator=x").needs_difficulties());
+        assert!(parse("mode=mania stars<5").retain(&mut set));
+        assert_eq!(set.difficulties.len(), 1);
+        assert!(!parse("keys=4").retain(&mut set));
+    }
+}
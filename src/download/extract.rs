@@ -0,0 +1,167 @@
+//! Unpack downloaded `.osz` files into the `Songs` directory of osu!.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::core::osz::OszInfo;
+
+/// Where and how to extract downloaded sets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extract {
+    /// The `Songs` directory.
+    pub songs: PathBuf,
+    /// Delete the `.osz` after extracting.
+    pub delete_osz: bool,
+}
+
+impl Extract {
+    pub fn new(songs: impl Into<PathBuf>) -> Self {
+        Self {
+            songs: songs.into(),
+            delete_osz: false,
+        }
+    }
+
+    pub fn delete_osz(mut self, delete_osz: bool) -> Self {
+        self.delete_osz = delete_osz;
+        self
+    }
+
+    /// Unpack `osz` of `sid` into `Songs/<sid> <Artist> - <Title>/`, returning
+    /// the folder.
+    ///
+    /// If the folder exists, a number is appended to the name. Entries
+    /// escaping the folder (zip slip) are skipped. The archive is unpacked
+    /// into a temporary folder first, so a failure leaves no partial folder.
+    pub fn extract(&self, sid: u32, osz: &Path) -> Result<PathBuf> {
+        let info = OszInfo::read(osz)?;
+        let metadata = info.metadata();
+        let name = sanitize(&format!("{sid} {} - {}", metadata.artist, metadata.title));
+        fs::create_dir_all(&self.songs)?;
+        let target = unique_path(&self.songs, &name);
+        let tmp = self.songs.join(format!(".{sid}.extracting"));
+        if tmp.exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        let result = unzip(osz, &tmp).and_then(|()| Ok(fs::rename(&tmp, &target)?));
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&tmp);
+            return Err(e.context(format!("failed to extract {}", osz.display())));
+        }
+        if self.delete_osz {
+            fs::remove_file(osz)?;
+        }
+        Ok(target)
+    }
+}
+
+fn unzip(osz: &Path, dir: &Path) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(BufReader::new(File::open(osz)?))?;
+    fs::create_dir_all(dir)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let Some(name) = entry.enclosed_name().map(Path::to_path_buf) else {
+            tracing::warn!(osz = %osz.display(), name = entry.name(), "skip unsafe entry");
+            continue;
+        };
+        let path = dir.join(name);
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file =
+            File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+        io::copy(&mut entry, &mut file)?;
+    }
+    Ok(())
+}
+
+/// Replace characters illegal in file names on Windows, which osu! runs on.
+pub fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces
+    name.trim_end_matches(['.', ' ']).to_string()
+}
+
+/// `dir/name`, or `dir/name (n)` with the smallest n not existing.
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+    (1..)
+        .map(|n| dir.join(format!("{name} ({n})")))
+        .find(|path| !path.exists())
+        .expect("infinite candidates")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::core::osz::tests::osz;
+
+    #[test]
+    fn test_extract() {
+        let dir = tempfile::tempdir().unwrap();
+        let songs = dir.path().join("Songs");
+        let path = dir.path().join("1.osz");
+        fs::write(&path, osz(1)).unwrap();
+
+        let extract = Extract::new(&songs);
+        let folder = extract.extract(1, &path).unwrap();
+        assert_eq!(folder, songs.join("1 Artist - Title"));
+        assert!(folder.join("Artist - Title (Mapper) [Hard].osu").is_file());
+        assert_eq!(fs::read(folder.join("audio.mp3")).unwrap().len(), 4096);
+        assert!(path.exists());
+
+        let folder = extract.delete_osz(true).extract(1, &path).unwrap();
+        assert_eq!(folder, songs.join("1 Artist - Title (1)"));
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(&songs).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_zip_slip() {
+        let dir = tempfile::tempdir().unwrap();
+        let songs = dir.path().join("a").join("Songs");
+        let path = dir.path().join("1.osz");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for name in ["a.osu", "../../evil.txt", "/abs.txt", "sb/ok.png"] {
+            zip.start_file(name, Default::default()).unwrap();
+            zip.write_all(b"osu file format v14\n").unwrap();
+        }
+        zip.finish().unwrap();
+
+        let folder = Extract::new(&songs).extract(1, &path).unwrap();
+        assert!(folder.join("a.osu").is_file());
+        assert!(folder.join("sb/ok.png").is_file());
+        assert!(!dir.path().join("evil.txt").exists());
+        assert!(!Path::new("/abs.txt").exists());
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(
+            sanitize("1 A/B - What?: <Remix>*."),
+            "1 A_B - What__ _Remix__"
+        );
+        assert_eq!(sanitize("1 紅 - \"x\"|\ty"), "1 紅 - _x___y");
+    }
+}
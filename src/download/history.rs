@@ -0,0 +1,183 @@
+//! History of download attempts, kept as JSON lines across runs.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::core::mirror::Variant;
+
+/// How a download attempt ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Done,
+    Failed,
+}
+
+/// One download attempt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub sid: u32,
+    /// The mirror the set is downloaded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
+    pub variant: Variant,
+    /// Size of the `.osz` in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// MD5 of the `.osz`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Unix timestamp.
+    pub timestamp: i64,
+    pub outcome: Outcome,
+    /// Where the set is saved, or the error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn done(sid: u32, variant: Variant, path: PathBuf) -> Self {
+        Self {
+            sid,
+            mirror: None,
+            variant,
+            size: None,
+            hash: None,
+            timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+            outcome: Outcome::Done,
+            path: Some(path),
+            error: None,
+        }
+    }
+
+    pub fn failed(sid: u32, variant: Variant, error: String) -> Self {
+        Self {
+            outcome: Outcome::Failed,
+            path: None,
+            error: Some(error),
+            ..Self::done(sid, variant, PathBuf::new())
+        }
+    }
+}
+
+/// The history file, appended by every attempt.
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    entries: Vec<HistoryEntry>,
+    /// Sets downloaded successfully before, by set id.
+    completed: HashMap<u32, PathBuf>,
+    lock: Mutex<()>,
+}
+
+impl History {
+    /// `history.jsonl` in the user data directory.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(
+            dirs::data_dir()?
+                .join("beatmap-downloader")
+                .join("history.jsonl"),
+        )
+    }
+
+    /// Read the history at `path`, which may not exist yet. Malformed lines
+    /// are skipped.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut entries: Vec<HistoryEntry> = vec![];
+        match File::open(path) {
+            Ok(file) => {
+                for (i, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line?;
+                    match serde_json::from_str(&line) {
+                        Ok(entry) => entries.push(entry),
+                        Err(e) if !line.trim().is_empty() => {
+                            tracing::warn!(line = i + 1, error = %e, "skip history line")
+                        }
+                        Err(_) => {}
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+        let completed = entries
+            .iter()
+            .filter(|entry| entry.outcome == Outcome::Done)
+            .filter_map(|entry| Some((entry.sid, entry.path.clone()?)))
+            .collect();
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+            completed,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Entries read when opened.
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Where `sid` was saved if downloaded successfully in an earlier run.
+    pub fn completed(&self, sid: u32) -> Option<&Path> {
+        self.completed.get(&sid).map(PathBuf::as_path)
+    }
+
+    /// Append `entry` to the file.
+    pub fn record(&self, entry: &HistoryEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let _lock = self.lock.lock().unwrap();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        file.write_all(&line)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data").join("history.jsonl");
+        let history = History::open(&path).unwrap();
+        assert!(history.entries().is_empty());
+
+        let mut done = HistoryEntry::done(1, Variant::Mini, "1.osz".into());
+        done.mirror = Some("sayobot".to_string());
+        done.size = Some(1024);
+        history.record(&done).unwrap();
+        let failed = HistoryEntry::failed(2, Variant::Full, "404".to_string());
+        history.record(&failed).unwrap();
+        // a line broken by a crash
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"sid\":3,\n").unwrap();
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.entries(), [done, failed]);
+        assert_eq!(history.completed(1), Some(Path::new("1.osz")));
+        assert_eq!(history.completed(2), None);
+        let line = fs::read_to_string(&path).unwrap();
+        assert!(line.starts_with(r#"{"sid":1,"mirror":"sayobot","variant":"mini","size":1024,"#));
+    }
+}
@@ -0,0 +1,520 @@
+pub mod extract;
+pub mod history;
+pub mod queue;
+#[cfg(test)]
+mod test_server;
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use reqwest::{
+    header::{CONTENT_RANGE, RANGE},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+    sync::mpsc::UnboundedSender,
+};
+
+use crate::core::osz::{InvalidOsz, OszInfo};
+
+/// Minimal interval between two [`DownloadEvent::Progress`] of one download.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Result of downloading one beatmap set.
+#[derive(Debug)]
+pub struct DownloadReport {
+    pub sid: u32,
+    /// Path of the downloaded `.osz` file.
+    pub result: Result<PathBuf>,
+    /// The set is already in the library and not downloaded again.
+    pub skipped: bool,
+}
+
+/// Progress of downloads, serialized as an object tagged by `event`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DownloadEvent {
+    Queued {
+        sid: u32,
+    },
+    Progress {
+        sid: u32,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    /// The body is received, checking the file.
+    Verifying {
+        sid: u32,
+    },
+    /// The `.osz` is valid, with these difficulties.
+    Verified {
+        sid: u32,
+        /// Serialized as the names of the difficulties.
+        #[serde(rename = "difficulties", serialize_with = "difficulties")]
+        info: Box<OszInfo>,
+    },
+    Done {
+        sid: u32,
+        path: PathBuf,
+    },
+    /// The set is already in the library at `path`.
+    Skipped {
+        sid: u32,
+        path: PathBuf,
+    },
+    Failed {
+        sid: u32,
+        kind: ErrorKind,
+        error: String,
+    },
+}
+
+fn difficulties<S: Serializer>(info: &OszInfo, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        info.beatmaps
+            .iter()
+            .map(|beatmap| &beatmap.metadata.version),
+    )
+}
+
+/// Why a download failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// No mirror has the set.
+    NotFound,
+    /// A mirror responded another unsuccessful status code.
+    Http,
+    /// The connection failed or timed out.
+    Network,
+    /// The downloaded file is not a valid `.osz`.
+    InvalidOsz,
+    /// Reading or writing local files failed.
+    Io,
+    Cancelled,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn of(error: &anyhow::Error) -> Self {
+        if error.is::<Cancelled>() {
+            Self::Cancelled
+        } else if error.is::<InvalidOsz>() {
+            Self::InvalidOsz
+        } else if let Some(HttpStatus(status)) = error.downcast_ref() {
+            if *status == StatusCode::NOT_FOUND {
+                Self::NotFound
+            } else {
+                Self::Http
+            }
+        } else if error.is::<reqwest::Error>() {
+            Self::Network
+        } else if error.is::<std::io::Error>() {
+            Self::Io
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// The download is cancelled by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// The server responds an unsuccessful status code. Downloads failed with it
+/// are not retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpStatus(pub StatusCode);
+
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Request failed with status code: {}", self.0)
+    }
+}
+
+impl std::error::Error for HttpStatus {}
+
+/// Bookkeeping of a `.part` file, saved next to it, so that an interrupted
+/// download can be resumed in the next run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PartInfo {
+    url: String,
+    total: Option<u64>,
+}
+
+/// Downloads `.osz` files into a directory.
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    client: Client,
+    dir: PathBuf,
+    retries: u32,
+    events: Option<UnboundedSender<DownloadEvent>>,
+}
+
+impl Downloader {
+    /// Constructs a new instance of [`Downloader`].
+    pub fn new(client: Client, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            client,
+            dir: dir.into(),
+            retries: 3,
+            events: None,
+        }
+    }
+
+    /// Send progress of downloads to `events`.
+    pub fn with_events(mut self, events: UnboundedSender<DownloadEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn emit(&self, event: DownloadEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Set how many times a broken download is resumed before giving up.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Final path of the `.osz` of `sid`.
+    pub fn target_path(&self, sid: u32) -> PathBuf {
+        self.dir.join(format!("{sid}.osz"))
+    }
+
+    /// Temporary path of the `.osz` of `sid` while downloading.
+    pub fn part_path(&self, sid: u32) -> PathBuf {
+        self.dir.join(format!("{sid}.osz.part"))
+    }
+
+    fn part_info_path(&self, sid: u32) -> PathBuf {
+        self.dir.join(format!("{sid}.osz.part.json"))
+    }
+
+    /// Download the beatmap set `sid` from `url`.
+    ///
+    /// The content is streamed into a `.part` file first, which is renamed to
+    /// the target path only after the whole body is received and validated
+    /// as an `.osz`. A broken download is resumed from the end of the `.part`
+    /// file with a `Range` request, both on retry and in the next run. Server
+    /// errors (5xx) and 429 are retried too; other statuses and an invalid
+    /// `.osz`, which is deleted, are not.
+    pub async fn download(&self, sid: u32, url: &str) -> Result<PathBuf> {
+        let target = self.target_path(sid);
+        if fs::try_exists(&target).await? {
+            return Ok(target);
+        }
+        fs::create_dir_all(&self.dir).await?;
+
+        let mut attempt = 0;
+        while let Err(e) = self.try_download(sid, url).await {
+            if attempt >= self.retries || !retryable(&e) {
+                return Err(e);
+            }
+            tracing::debug!(sid, url, attempt, error = %e, "retry download");
+            attempt += 1;
+            tokio::time::sleep(Duration::from_millis(200 * attempt as u64)).await;
+        }
+        self.emit(DownloadEvent::Verifying { sid });
+        let info = match self.verify(sid).await {
+            Ok(info) => info,
+            Err(e) => {
+                let _ = fs::remove_file(self.part_path(sid)).await;
+                let _ = fs::remove_file(self.part_info_path(sid)).await;
+                return Err(e);
+            }
+        };
+        self.emit(DownloadEvent::Verified {
+            sid,
+            info: Box::new(info),
+        });
+        fs::rename(self.part_path(sid), &target).await?;
+        let _ = fs::remove_file(self.part_info_path(sid)).await;
+        Ok(target)
+    }
+
+    /// Validate the complete `.part` file of `sid`.
+    async fn verify(&self, sid: u32) -> Result<OszInfo> {
+        let part = self.part_path(sid);
+        let info = tokio::task::spawn_blocking(move || OszInfo::read(&part)).await??;
+        match info.sid() {
+            Some(id) if id != sid => bail!(InvalidOsz(format!("it is set {id}"))),
+            _ => Ok(info),
+        }
+    }
+
+    /// Fill the `.part` file of `sid` until it is complete.
+    async fn try_download(&self, sid: u32, url: &str) -> Result<()> {
+        let part = self.part_path(sid);
+        let info_path = self.part_info_path(sid);
+        let mut offset = self.resume_offset(sid, url).await;
+        let (mut res, mut file) = loop {
+            let mut req = self.client.get(url);
+            if offset > 0 {
+                req = req.header(RANGE, format!("bytes={offset}-"));
+            }
+            tracing::debug!(url, offset, "download request");
+            let res = req.send().await?;
+            let status = res.status();
+            tracing::debug!(url, %status, length = res.content_length(), "download response");
+            if offset > 0 && status == StatusCode::PARTIAL_CONTENT {
+                if content_range_start(&res) == Some(offset) {
+                    let file = OpenOptions::new().append(true).open(&part).await?;
+                    break (res, file);
+                }
+            } else if status.is_success() {
+                break (res, fs::File::create(&part).await?);
+            } else if offset == 0 || status != StatusCode::RANGE_NOT_SATISFIABLE {
+                bail!(HttpStatus(status));
+            }
+            // The server cannot resume the `.part` file: download it again.
+            offset = 0;
+        };
+
+        let info = PartInfo {
+            url: url.to_string(),
+            total: res.content_length().map(|len| offset + len),
+        };
+        fs::write(&info_path, serde_json::to_vec(&info)?).await?;
+
+        let mut downloaded = offset;
+        let mut last_emit = Instant::now();
+        self.emit(DownloadEvent::Progress {
+            sid,
+            downloaded,
+            total: info.total,
+        });
+        while let Some(chunk) = res.chunk().await? {
+            file.write_all(&chunk).await?;
+            downloaded += chunk.len() as u64;
+            if last_emit.elapsed() >= PROGRESS_INTERVAL {
+                last_emit = Instant::now();
+                self.emit(DownloadEvent::Progress {
+                    sid,
+                    downloaded,
+                    total: info.total,
+                });
+            }
+        }
+        file.flush().await?;
+        self.emit(DownloadEvent::Progress {
+            sid,
+            downloaded,
+            total: info.total,
+        });
+        Ok(())
+    }
+
+    /// The length of the `.part` file of `sid` if it was downloaded from
+    /// `url` and can be resumed, otherwise 0.
+    async fn resume_offset(&self, sid: u32, url: &str) -> u64 {
+        let Ok(len) = fs::metadata(self.part_path(sid)).await.map(|m| m.len()) else {
+            return 0;
+        };
+        let Ok(info) = fs::read(self.part_info_path(sid)).await else {
+            return 0;
+        };
+        match serde_json::from_slice::<PartInfo>(&info) {
+            Ok(info) if info.url == url && info.total.is_none_or(|total| len <= total) => len,
+            _ => 0,
+        }
+    }
+}
+
+/// Whether a failed download may succeed if tried again.
+fn retryable(error: &anyhow::Error) -> bool {
+    match error.downcast_ref() {
+        Some(HttpStatus(status)) => {
+            status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+        }
+        None => !error.is::<InvalidOsz>(),
+    }
+}
+
+/// Parse the start of `Content-Range: bytes <start>-<end>/<total>`.
+fn content_range_start(res: &reqwest::Response) -> Option<u64> {
+    res.headers()
+        .get(CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::osz::tests::osz;
+    use test_server::{Route, TestServer};
+
+    #[tokio::test]
+    async fn test_download() {
+        let server = TestServer::start([("/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let downloader = Downloader::new(Client::new(), dir.path());
+
+        let path = downloader.download(1, &server.url("/1")).await.unwrap();
+        assert_eq!(path, dir.path().join("1.osz"));
+        assert_eq!(std::fs::read(&path).unwrap(), osz(1));
+        assert!(!downloader.part_path(1).exists());
+
+        let err = downloader.download(2, &server.url("/2")).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<HttpStatus>(),
+            Some(&HttpStatus(StatusCode::NOT_FOUND))
+        );
+        assert!(!downloader.target_path(2).exists());
+        // not retried
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_status() {
+        let server = TestServer::start_with([
+            Route::new("/1", osz(1)).fail_once(503),
+            Route::new("/2", osz(2)).fail_once(429),
+            Route::new("/3", osz(3)).fail_once(403),
+        ])
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        let downloader = Downloader::new(Client::new(), dir.path());
+
+        assert!(downloader.download(1, &server.url("/1")).await.is_ok());
+        assert!(downloader.download(2, &server.url("/2")).await.is_ok());
+        let err = downloader.download(3, &server.url("/3")).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<HttpStatus>(),
+            Some(&HttpStatus(StatusCode::FORBIDDEN))
+        );
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_resume_on_retry() {
+        let body = osz(1);
+        let server = TestServer::start_with([Route::new("/1", body.clone()).cut_once(1000)]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let downloader = Downloader::new(Client::new(), dir.path());
+
+        let path = downloader.download(1, &server.url("/1")).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), body);
+        assert_eq!(
+            server.requests(),
+            [
+                ("/1".to_string(), None),
+                ("/1".to_string(), Some("bytes=1000-".to_string()))
+            ]
+        );
+        assert!(!downloader.part_info_path(1).exists());
+    }
+
+    #[tokio::test]
+    async fn test_resume_next_run() {
+        let body = osz(1);
+        let server = TestServer::start_with([Route::new("/1", body.clone()).cut_once(1000)]).await;
+        let dir = tempfile::tempdir().unwrap();
+
+        let downloader = Downloader::new(Client::new(), dir.path()).retries(0);
+        assert!(downloader.download(1, &server.url("/1")).await.is_err());
+        assert_eq!(std::fs::read(downloader.part_path(1)).unwrap().len(), 1000);
+
+        let downloader = Downloader::new(Client::new(), dir.path());
+        let path = downloader.download(1, &server.url("/1")).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), body);
+        assert_eq!(server.requests()[1].1.as_deref(), Some("bytes=1000-"));
+    }
+
+    #[tokio::test]
+    async fn test_server_ignores_range() {
+        let body = osz(1);
+        let server =
+            TestServer::start_with([Route::new("/1", body.clone()).no_range().cut_once(1000)])
+                .await;
+        let dir = tempfile::tempdir().unwrap();
+        let downloader = Downloader::new(Client::new(), dir.path());
+
+        let path = downloader.download(1, &server.url("/1")).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), body);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_range_not_satisfiable() {
+        let server = TestServer::start([("/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let downloader = Downloader::new(Client::new(), dir.path());
+        let url = server.url("/1");
+        // longer than the file on the server
+        std::fs::write(downloader.part_path(1), vec![0; osz(1).len() + 1]).unwrap();
+        std::fs::write(
+            downloader.part_info_path(1),
+            serde_json::to_vec(&PartInfo {
+                url: url.clone(),
+                total: None,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let path = downloader.download(1, &url).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), osz(1));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_osz() {
+        let server = TestServer::start([
+            ("/1", b"<html>503 Service Unavailable</html>".to_vec()),
+            ("/2", osz(3)),
+        ])
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let downloader = Downloader::new(Client::new(), dir.path()).with_events(sender);
+
+        let err = downloader.download(1, &server.url("/1")).await.unwrap_err();
+        assert!(err.is::<InvalidOsz>());
+        assert!(!downloader.part_path(1).exists());
+        assert!(!downloader.part_info_path(1).exists());
+        assert!(!downloader.target_path(1).exists());
+        assert_eq!(server.requests().len(), 1);
+        // the set id in the difficulties does not match
+        let err = downloader.download(2, &server.url("/2")).await.unwrap_err();
+        assert!(err.is::<InvalidOsz>());
+
+        let server = TestServer::start([("/1", osz(1))]).await;
+        downloader.download(1, &server.url("/1")).await.unwrap();
+        let mut verified = None;
+        while let Ok(event) = receiver.try_recv() {
+            if let DownloadEvent::Verified { sid: 1, info } = event {
+                verified = Some(info);
+            }
+        }
+        assert_eq!(verified.unwrap().beatmaps.len(), 2);
+    }
+}
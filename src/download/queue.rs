@@ -0,0 +1,552 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use md5::{Digest, Md5};
+use reqwest::{StatusCode, Url};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    extract::Extract,
+    history::{History, HistoryEntry},
+    Cancelled, DownloadEvent, DownloadReport, Downloader, ErrorKind, HttpStatus,
+};
+use crate::core::{
+    library::Library,
+    mirror::{Mirrors, Variant},
+};
+
+/// Semaphores limiting concurrent downloads of each host.
+type Hosts = Arc<Mutex<HashMap<String, Arc<Semaphore>>>>;
+
+/// A queue downloading beatmap sets concurrently.
+///
+/// At most `workers` downloads run at the same time, and at most `per_host`
+/// of them go to the same host. Each set is tried on every mirror in failover
+/// order until one succeeds, falling back to the full variant on a mirror
+/// without the requested one. Sets already in the library or downloaded in
+/// the history are skipped, and downloaded ones are extracted if asked.
+///
+/// Clones share the limits, so runs of clones of one queue together keep
+/// within them.
+#[derive(Debug, Clone)]
+pub struct DownloadQueue {
+    downloader: Downloader,
+    mirrors: Arc<Mirrors>,
+    workers: Arc<Semaphore>,
+    per_host: usize,
+    hosts: Hosts,
+    cancel: CancellationToken,
+    library: Arc<Library>,
+    extract: Option<Extract>,
+    variant: Variant,
+    history: Option<Arc<History>>,
+}
+
+impl DownloadQueue {
+    /// Constructs a new instance of [`DownloadQueue`].
+    pub fn new(downloader: Downloader, mirrors: Arc<Mirrors>) -> Self {
+        Self {
+            downloader,
+            mirrors,
+            workers: Arc::new(Semaphore::new(4)),
+            per_host: 2,
+            hosts: Default::default(),
+            cancel: CancellationToken::new(),
+            library: Default::default(),
+            extract: None,
+            variant: Variant::Full,
+            history: None,
+        }
+    }
+
+    /// Set the number of concurrent downloads.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = Arc::new(Semaphore::new(workers.max(1)));
+        self
+    }
+
+    /// Set the number of concurrent downloads to the same host.
+    pub fn per_host(mut self, per_host: usize) -> Self {
+        self.per_host = per_host.max(1);
+        self.hosts = Default::default();
+        self
+    }
+
+    /// Stop the queue when `cancel` is cancelled.
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Skip sets owned in `library`.
+    pub fn with_library(mut self, library: Arc<Library>) -> Self {
+        self.library = library;
+        self
+    }
+
+    /// Extract downloaded sets, reporting the folders instead of `.osz`.
+    pub fn extract(mut self, extract: Extract) -> Self {
+        self.extract = Some(extract);
+        self
+    }
+
+    /// Set which files of the sets to download.
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Skip sets downloaded in `history` and still on disk, and record every
+    /// attempt in it.
+    pub fn with_history(mut self, history: Arc<History>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Download all `sids`, returning reports in the order of `sids`.
+    ///
+    /// Cancelling the token aborts in-flight downloads, leaving their `.part`
+    /// files on disk; sets not started yet are reported as cancelled.
+    pub async fn run(&self, sids: Vec<u32>) -> Vec<DownloadReport> {
+        let mut set = JoinSet::new();
+        let mut reports = vec![];
+
+        for &sid in &sids {
+            self.downloader.emit(DownloadEvent::Queued { sid });
+        }
+        for (index, sid) in sids.into_iter().enumerate() {
+            let owned = self.library.get(sid).or_else(|| {
+                self.history
+                    .as_ref()
+                    .and_then(|history| history.completed(sid))
+                    .filter(|path| path.exists())
+            });
+            if let Some(path) = owned {
+                self.downloader.emit(DownloadEvent::Skipped {
+                    sid,
+                    path: path.to_path_buf(),
+                });
+                let result = Ok(path.to_path_buf());
+                reports.push((
+                    index,
+                    DownloadReport {
+                        sid,
+                        result,
+                        skipped: true,
+                    },
+                ));
+                continue;
+            }
+            let queue = self.clone();
+            set.spawn(async move {
+                let result = tokio::select! {
+                    _ = queue.cancel.cancelled() => Err(Cancelled.into()),
+                    result = queue.process(sid) => result,
+                };
+                queue.downloader.emit(match &result {
+                    Ok(path) => DownloadEvent::Done {
+                        sid,
+                        path: path.clone(),
+                    },
+                    Err(e) => DownloadEvent::Failed {
+                        sid,
+                        kind: ErrorKind::of(e),
+                        error: format!("{e:#}"),
+                    },
+                });
+                (
+                    index,
+                    DownloadReport {
+                        sid,
+                        result,
+                        skipped: false,
+                    },
+                )
+            });
+        }
+
+        while let Some(res) = set.join_next().await {
+            reports.push(res.expect("download task panicked"));
+        }
+        reports.sort_by_key(|(index, _)| *index);
+        reports.into_iter().map(|(_, report)| report).collect()
+    }
+
+    /// Download, extract and record `sid`, each step holding a worker.
+    async fn process(&self, sid: u32) -> Result<PathBuf> {
+        let (path, mirror, variant) = match self.download(sid).await {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                let entry = HistoryEntry::failed(sid, self.variant, format!("{e:#}"));
+                self.record(entry).await;
+                return Err(e);
+            }
+        };
+        let _worker = self.workers.acquire().await?;
+        let osz = path.clone();
+        let (size, hash) = tokio::task::spawn_blocking(move || {
+            let mut file = std::fs::File::open(osz).ok()?;
+            let size = file.metadata().ok()?.len();
+            let mut md5 = Md5::new();
+            std::io::copy(&mut file, &mut md5).ok()?;
+            Some((size, format!("{:x}", md5.finalize())))
+        })
+        .await?
+        .unzip();
+        let path = match self.extract.clone() {
+            Some(extract) => {
+                tokio::task::spawn_blocking(move || extract.extract(sid, &path)).await?
+            }
+            None => Ok(path),
+        };
+        let mut entry = match &path {
+            Ok(path) => HistoryEntry::done(sid, variant, path.clone()),
+            Err(e) => HistoryEntry::failed(sid, variant, format!("{e:#}")),
+        };
+        entry.mirror = Some(mirror);
+        entry.size = size;
+        entry.hash = hash;
+        self.record(entry).await;
+        path
+    }
+
+    async fn record(&self, entry: HistoryEntry) {
+        let Some(history) = self.history.clone() else {
+            return;
+        };
+        let result = tokio::task::spawn_blocking(move || history.record(&entry)).await;
+        if let Err(e) = result.map_err(anyhow::Error::from).and_then(|r| r) {
+            tracing::warn!(error = %format!("{e:#}"), "failed to record history");
+        }
+    }
+
+    /// Download `sid` from mirrors in failover order, returning the path, the
+    /// name of the mirror and the variant downloaded.
+    ///
+    /// A mirror not having the set (404) does not count as unhealthy. If it
+    /// does not have the variant, the full set is tried on it. The host is
+    /// waited for before taking a worker, so that a busy host does not hold
+    /// workers other hosts could use.
+    async fn download(&self, sid: u32) -> Result<(PathBuf, String, Variant)> {
+        let mut last_error = anyhow!("no mirror available");
+        let mut variants = vec![self.variant];
+        if self.variant != Variant::Full {
+            variants.push(Variant::Full);
+        }
+        for index in self.mirrors.order() {
+            let mirror = self.mirrors.get(index);
+            let urls = variants
+                .iter()
+                .filter_map(|&variant| Some((mirror.download_url(sid, variant)?, variant)));
+            for (url, variant) in urls {
+                if self.cancel.is_cancelled() {
+                    return Err(Cancelled.into());
+                }
+                let host = self.host_semaphore(&url);
+                let _host = host.acquire().await?;
+                let _worker = self.workers.acquire().await?;
+                match self.downloader.download(sid, &url).await {
+                    Ok(path) => {
+                        self.mirrors.report_success(index);
+                        return Ok((path, mirror.name().to_string(), variant));
+                    }
+                    Err(e) => {
+                        tracing::warn!(sid, url, mirror = mirror.name(), error = %e, "download failed");
+                        let not_found =
+                            e.downcast_ref() == Some(&HttpStatus(StatusCode::NOT_FOUND));
+                        last_error = e.context(format!("mirror {}", mirror.name()));
+                        if !not_found {
+                            self.mirrors.report_failure(index);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    fn host_semaphore(&self, url: &str) -> Arc<Semaphore> {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        self.hosts
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::mirror::{sayobot::Sayobot, Mirror},
+        core::osz::tests::osz,
+        download::test_server::TestServer,
+    };
+    use reqwest::Client;
+
+    fn mirrors(servers: &[&TestServer]) -> Arc<Mirrors> {
+        Arc::new(Mirrors::new(
+            servers
+                .iter()
+                .map(|server| Box::new(Sayobot::with_base(&server.url(""))) as Box<dyn Mirror>)
+                .collect(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_queue() {
+        let server =
+            TestServer::start((1..=5).map(|i| (format!("/beatmaps/download/full/{i}"), osz(i))))
+                .await;
+        let dir = tempfile::tempdir().unwrap();
+        let queue = DownloadQueue::new(
+            Downloader::new(Client::new(), dir.path()),
+            mirrors(&[&server]),
+        )
+        .workers(3)
+        .per_host(2);
+
+        let reports = queue.run((1..=6).collect()).await;
+        assert_eq!(
+            reports.iter().map(|r| r.sid).collect::<Vec<_>>(),
+            (1..=6).collect::<Vec<_>>()
+        );
+        assert!(reports[..5].iter().all(|r| r.result.is_ok()));
+        assert!(reports[5].result.is_err());
+
+        // clones share the limits
+        let _permits = queue.workers.acquire_many(3).await.unwrap();
+        assert_eq!(queue.clone().workers.available_permits(), 0);
+        let host = queue.host_semaphore(&server.url(""));
+        assert!(Arc::ptr_eq(
+            &host,
+            &queue.clone().host_semaphore(&server.url(""))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let empty = TestServer::start::<String>([]).await;
+        let server = TestServer::start([("/beatmaps/download/full/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let mirrors = mirrors(&[&empty, &server]);
+        let queue = DownloadQueue::new(Downloader::new(Client::new(), dir.path()), mirrors.clone());
+
+        let reports = queue.run(vec![1]).await;
+        assert!(reports[0].result.is_ok());
+        assert_eq!(empty.requests().len(), 1);
+        // 404 does not make a mirror unhealthy.
+        assert!(mirrors.is_healthy(0));
+    }
+
+    #[tokio::test]
+    async fn test_failover_invalid_osz() {
+        let html = b"<html>error</html>".to_vec();
+        let broken = TestServer::start([("/beatmaps/download/full/1", html)]).await;
+        let server = TestServer::start([("/beatmaps/download/full/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let queue = DownloadQueue::new(
+            Downloader::new(Client::new(), dir.path()),
+            mirrors(&[&broken, &server]),
+        );
+
+        let reports = queue.run(vec![1]).await;
+        assert!(reports[0].result.is_ok());
+        assert_eq!(broken.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_variant_fallback() {
+        let no_mini = TestServer::start([("/beatmaps/download/full/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let queue = DownloadQueue::new(
+            Downloader::new(Client::new(), dir.path()),
+            mirrors(&[&no_mini]),
+        )
+        .variant(Variant::Mini)
+        .with_history(Arc::new(History::open(&path).unwrap()));
+
+        let reports = queue.run(vec![1]).await;
+        assert!(reports[0].result.is_ok());
+        assert_eq!(
+            no_mini
+                .requests()
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+            ["/beatmaps/download/mini/1", "/beatmaps/download/full/1"]
+        );
+        // the variant actually downloaded is recorded
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.entries()[0].variant, Variant::Full);
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let server = TestServer::start([("/beatmaps/download/full/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let queue = DownloadQueue::new(
+            Downloader::new(Client::new(), dir.path()).with_events(sender),
+            mirrors(&[&server]),
+        );
+        queue.run(vec![1, 2]).await;
+
+        let mut events = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        assert_eq!(
+            events[..2],
+            [
+                DownloadEvent::Queued { sid: 1 },
+                DownloadEvent::Queued { sid: 2 }
+            ]
+        );
+        let len = osz(1).len() as u64;
+        assert!(events.contains(&DownloadEvent::Progress {
+            sid: 1,
+            downloaded: len,
+            total: Some(len)
+        }));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, DownloadEvent::Verified { sid: 1, .. })));
+        assert!(events.contains(&DownloadEvent::Done {
+            sid: 1,
+            path: dir.path().join("1.osz")
+        }));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            DownloadEvent::Failed {
+                sid: 2,
+                kind: ErrorKind::NotFound,
+                ..
+            }
+        )));
+
+        let verified = events
+            .iter()
+            .find(|e| matches!(e, DownloadEvent::Verified { .. }))
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(verified).unwrap(),
+            r#"{"event":"verified","sid":1,"difficulties":["Easy","Hard"]}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let server = TestServer::start([("/beatmaps/download/full/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let cancel = CancellationToken::new();
+        let queue = DownloadQueue::new(
+            Downloader::new(Client::new(), dir.path()),
+            mirrors(&[&server]),
+        )
+        .with_cancel(cancel.clone());
+        cancel.cancel();
+
+        let reports = queue.run(vec![1]).await;
+        let err = reports[0].result.as_ref().unwrap_err();
+        assert_eq!(ErrorKind::of(err), ErrorKind::Cancelled);
+        assert!(!dir.path().join("1.osz").exists());
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let server = TestServer::start([("/beatmaps/download/full/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let songs = dir.path().join("Songs");
+        let queue = DownloadQueue::new(
+            Downloader::new(Client::new(), dir.path()),
+            mirrors(&[&server]),
+        )
+        .extract(Extract::new(&songs).delete_osz(true));
+
+        let reports = queue.run(vec![1]).await;
+        let folder = reports[0].result.as_ref().unwrap();
+        assert_eq!(folder, &songs.join("1 Artist - Title"));
+        assert!(folder.join("audio.mp3").exists());
+        assert!(!dir.path().join("1.osz").exists());
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let server = TestServer::start([("/beatmaps/download/full/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let queue = |history: &Arc<History>| {
+            DownloadQueue::new(
+                Downloader::new(Client::new(), dir.path()),
+                mirrors(&[&server]),
+            )
+            .with_history(history.clone())
+        };
+
+        let history = Arc::new(History::open(&path).unwrap());
+        let reports = queue(&history).run(vec![1, 2]).await;
+        assert!(reports[0].result.is_ok() && reports[1].result.is_err());
+        let history = Arc::new(History::open(&path).unwrap());
+        let entries = history.entries();
+        assert_eq!(entries.len(), 2);
+        let done = entries.iter().find(|entry| entry.sid == 1).unwrap();
+        assert_eq!(done.mirror.as_deref(), Some("sayobot"));
+        assert_eq!(done.size, Some(osz(1).len() as u64));
+        assert_eq!(done.hash.as_ref().unwrap().len(), 32);
+
+        // the next run skips the downloaded set
+        let reports = queue(&history).run(vec![1]).await;
+        assert!(reports[0].skipped);
+        assert_eq!(server.requests().len(), 2);
+
+        // but not once the file is gone
+        std::fs::remove_file(dir.path().join("1.osz")).unwrap();
+        let reports = queue(&history).run(vec![1]).await;
+        assert!(!reports[0].skipped && reports[0].result.is_ok());
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_skip_owned() {
+        let server = TestServer::start([("/beatmaps/download/full/1", osz(1))]).await;
+        let dir = tempfile::tempdir().unwrap();
+        let mut library = Library::default();
+        library.insert(2, PathBuf::from("Songs/2 Artist - Title"));
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let queue = DownloadQueue::new(
+            Downloader::new(Client::new(), dir.path()).with_events(sender),
+            mirrors(&[&server]),
+        )
+        .with_library(Arc::new(library));
+
+        let reports = queue.run(vec![1, 2]).await;
+        assert!(!reports[0].skipped);
+        assert!(reports[1].skipped);
+        assert_eq!(
+            reports[1].result.as_ref().unwrap(),
+            &PathBuf::from("Songs/2 Artist - Title")
+        );
+        assert_eq!(server.requests().len(), 1);
+        let mut events = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        assert!(events.contains(&DownloadEvent::Skipped {
+            sid: 2,
+            path: PathBuf::from("Songs/2 Artist - Title")
+        }));
+    }
+}
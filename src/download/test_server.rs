@@ -0,0 +1,190 @@
+//! A minimal HTTP server standing in for a mirror in tests.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// A file served by [`TestServer`].
+#[derive(Debug, Clone)]
+pub struct Route {
+    path: String,
+    body: Vec<u8>,
+    ranges: bool,
+    cut_once: Option<usize>,
+    fail_once: Option<u16>,
+}
+
+impl Route {
+    pub fn new(path: impl Into<String>, body: Vec<u8>) -> Self {
+        Self {
+            path: path.into(),
+            body,
+            ranges: true,
+            cut_once: None,
+            fail_once: None,
+        }
+    }
+
+    /// Ignore `Range` headers, always responding the full body.
+    pub fn no_range(mut self) -> Self {
+        self.ranges = false;
+        self
+    }
+
+    /// Drop the connection after sending `n` bytes of the body, only for the
+    /// first request.
+    pub fn cut_once(mut self, n: usize) -> Self {
+        self.cut_once = Some(n);
+        self
+    }
+
+    /// Respond `status` with no body, only for the first request.
+    pub fn fail_once(mut self, status: u16) -> Self {
+        self.fail_once = Some(status);
+        self
+    }
+}
+
+/// (path, `Range` header) of every request received.
+type Requests = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+pub struct TestServer {
+    addr: SocketAddr,
+    handle: tokio::task::JoinHandle<()>,
+    requests: Requests,
+}
+
+impl TestServer {
+    /// Serve `routes` (path -> body) on a random local port.
+    pub async fn start<P: Into<String>>(routes: impl IntoIterator<Item = (P, Vec<u8>)>) -> Self {
+        Self::start_with(routes.into_iter().map(|(p, b)| Route::new(p, b))).await
+    }
+
+    /// Serve `routes` on a random local port.
+    pub async fn start_with(routes: impl IntoIterator<Item = Route>) -> Self {
+        let routes: HashMap<String, Route> = routes
+            .into_iter()
+            .map(|route| (route.path.clone(), route))
+            .collect();
+        let routes = Arc::new(Mutex::new(routes));
+        let requests = Arc::new(Mutex::new(vec![]));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle(stream, routes.clone(), requests.clone()));
+                }
+            }
+        });
+        Self {
+            addr,
+            handle,
+            requests,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+
+    /// (path, `Range` header) of every request received.
+    pub fn requests(&self) -> Vec<(String, Option<String>)> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle(stream: TcpStream, routes: Arc<Mutex<HashMap<String, Route>>>, requests: Requests) {
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    if stream.read_line(&mut request_line).await.is_err() {
+        return;
+    }
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or("/")
+        .to_string();
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        match stream.read_line(&mut line).await {
+            Ok(n) if n > 0 && line != "\r\n" => {
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("range") {
+                        range = Some(value.trim().to_string());
+                    }
+                }
+            }
+            _ => break,
+        }
+    }
+    requests.lock().unwrap().push((path.clone(), range.clone()));
+
+    let route = routes.lock().unwrap().get_mut(&path).map(|route| {
+        let cut = route.cut_once.take();
+        let fail = route.fail_once.take();
+        (route.clone(), cut, fail)
+    });
+    let mut cut = None;
+    let response = match route {
+        Some((_, _, Some(status))) => {
+            format!("HTTP/1.1 {status} Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .into_bytes()
+        }
+        Some((route, cut_once, None)) => {
+            cut = cut_once;
+            let start = range
+                .filter(|_| route.ranges)
+                .and_then(|r| r.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok());
+            let total = route.body.len();
+            let mut res = match start {
+                Some(start) if start >= total => format!(
+                    "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{total}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .into_bytes(),
+                Some(start) => format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{}/{total}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    total - 1,
+                    total - start
+                )
+                .into_bytes(),
+                None => format!(
+                    "HTTP/1.1 200 OK\r\nAccept-Ranges: bytes\r\nContent-Length: {total}\r\nConnection: close\r\n\r\n"
+                )
+                .into_bytes(),
+            };
+            let body = match start {
+                Some(start) if start >= total => &[][..],
+                Some(start) => &route.body[start..],
+                None => &route.body[..],
+            };
+            res.extend_from_slice(&body[..cut.unwrap_or(body.len()).min(body.len())]);
+            res
+        }
+        None => {
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec()
+        }
+    };
+    let _ = stream.get_mut().write_all(&response).await;
+    if cut.is_some() {
+        // Let the client receive the partial body before the connection drops.
+        let _ = stream.get_mut().flush().await;
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    let _ = stream.get_mut().shutdown().await;
+}
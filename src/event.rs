@@ -5,8 +5,13 @@ use crossterm::event::{Event as CrosstermEvent, KeyEvent, MouseEvent};
 use futures::{FutureExt, StreamExt};
 use tokio::sync::mpsc;
 
+use crate::{
+    core::beatmap::{BeatmapList, BeatmapSet},
+    download::DownloadEvent,
+};
+
 /// Terminal events.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Event {
     /// Terminal tick.
     Tick,
@@ -16,6 +21,12 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
+    /// Response of a beatmap list query, with the generation of the query.
+    List(u64, Result<BeatmapList, String>),
+    /// A beatmap set with all its difficulties.
+    Detail(Box<BeatmapSet>),
+    /// Progress of a download.
+    Download(DownloadEvent),
 }
 
 /// Terminal event handler.
@@ -77,6 +88,11 @@ impl EventHandler {
         }
     }
 
+    /// A sender for background tasks to push events into the handler.
+    pub fn sender(&self) -> mpsc::UnboundedSender<Event> {
+        self.sender.clone()
+    }
+
     /// Receive the next event from the handler thread.
     ///
     /// This function will always block the current thread if
@@ -1,20 +1,306 @@
-use anyhow::Result;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Arc,
+};
 
-use crate::core::api::API;
-pub async fn info(api: &API) -> Result<()> {
+use anyhow::{bail, Result};
+use futures::{stream, StreamExt};
+use serde_json::json;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::Config,
+    core::{
+        api::{DownloadArgs, DownloadOptions, HistoryArgs, PackDownloadArgs, API},
+        collection::{beatmap_md5s, Collection, CollectionDb},
+        library::Library,
+        mirror::Mirrors,
+        pack::PackKind,
+    },
+    download::{
+        history::{History, Outcome},
+        queue::DownloadQueue,
+        DownloadReport, Downloader,
+    },
+    output::{print_json, CollectionRecord, Format, PackRecord, ReportRecord, SetRecord},
+};
+
+/// Print the beatmap list requested by `api` in `format`.
+pub async fn info(mirrors: &Mirrors, api: &API, format: Format) -> Result<()> {
+    let client = reqwest::Client::new();
+    let list = mirrors.query(&client, api).await?;
+    match format {
+        Format::Table => {
+            for set in &list.data {
+                println!(
+                    "{}\t{} - {} ({})\t[{}]",
+                    set.sid,
+                    set.display_artist(),
+                    set.display_title(),
+                    set.creator,
+                    set.approved
+                );
+            }
+        }
+        Format::Json => {
+            let records: Vec<_> = list.data.iter().map(SetRecord::from).collect();
+            print_json(&records)?;
+        }
+        Format::Ndjson => {
+            for set in &list.data {
+                print_json(&SetRecord::from(set))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Download every beatmap set in the list requested by `args.query`.
+pub async fn download(
+    mirrors: Arc<Mirrors>,
+    library: Arc<Library>,
+    history: Option<Arc<History>>,
+    config: &Config,
+    args: &DownloadArgs,
+) -> Result<()> {
+    let client = reqwest::Client::new();
+    let api = args.query.to_api();
+    let list = mirrors.query(&client, &api).await?;
+    let sids = list.data.iter().map(|set| set.sid).collect();
+    let name = api.name(OffsetDateTime::now_utc().date());
+    download_sets(mirrors, library, history, config, &args.options, sids, name).await
+}
+
+/// Print the official beatmap packs of `kind` in `format`.
+pub async fn packs(mirrors: &Mirrors, kind: PackKind, format: Format) -> Result<()> {
     let client = reqwest::Client::new();
-    dbg!(&api);
-    let res = client
-        .post("https://api.sayobot.cn/?post")
-        .json(api)
-        .send()
-        .await?;
-
-    if res.status().is_success() {
-        let json_body: serde_json::Value = res.json().await?;
-        println!("{:?}", json_body);
-    } else {
-        println!("Request failed with status code: {:?}", res.status());
+    let packs = mirrors.packs(&client, kind).await?;
+    match format {
+        Format::Table => {
+            for pack in &packs {
+                println!("{}\t{}\t{}", pack.tag, pack.name, pack.author);
+            }
+        }
+        Format::Json => {
+            let records: Vec<_> = packs.iter().map(PackRecord::from).collect();
+            print_json(&records)?;
+        }
+        Format::Ndjson => {
+            for pack in &packs {
+                print_json(&PackRecord::from(pack))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Download every beatmap set in the packs of `args`. Packs no mirror has are
+/// reported on stderr, and the sets of the others are downloaded anyway.
+pub async fn download_packs(
+    mirrors: Arc<Mirrors>,
+    library: Arc<Library>,
+    history: Option<Arc<History>>,
+    config: &Config,
+    args: &PackDownloadArgs,
+) -> Result<()> {
+    let client = reqwest::Client::new();
+    let tags = args.packs.iter().flat_map(|range| range.tags());
+    let packs: Vec<_> = stream::iter(tags)
+        .map(|tag| {
+            let (client, mirrors) = (&client, &mirrors);
+            async move {
+                let pack = mirrors.pack(client, &tag).await;
+                (tag, pack)
+            }
+        })
+        .buffered(4)
+        .collect()
+        .await;
+    let total = packs.len();
+    let mut missing = vec![];
+    let mut sids: Vec<u32> = vec![];
+    // sets in several packs are downloaded once, in the order of the packs
+    let mut seen = HashSet::new();
+    for (tag, pack) in packs {
+        match pack {
+            Ok(pack) => sids.extend(pack.sids.into_iter().filter(|&sid| seen.insert(sid))),
+            Err(e) => {
+                eprintln!("MISSING\t{tag}\t{e:#}");
+                missing.push(tag);
+            }
+        }
+    }
+    if missing.len() == total {
+        bail!("none of the {total} packs is available");
+    }
+    let ranges: Vec<_> = args.packs.iter().map(ToString::to_string).collect();
+    let name = format!("Packs {}", ranges.join(", "));
+    download_sets(mirrors, library, history, config, &args.options, sids, name).await?;
+    if !missing.is_empty() {
+        bail!(
+            "{} of {total} packs unavailable: {}",
+            missing.len(),
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Download the sets `sids` not in `library` or `history`, printing the
+/// result of each file, or every
+/// [`DownloadEvent`](crate::download::DownloadEvent) as it happens with
+/// [`Format::Ndjson`]. Downloaded sets are extracted if `config` asks to, and
+/// added to the collection `name` if `options` asks to.
+async fn download_sets(
+    mirrors: Arc<Mirrors>,
+    library: Arc<Library>,
+    history: Option<Arc<History>>,
+    config: &Config,
+    options: &DownloadOptions,
+    sids: Vec<u32>,
+    name: String,
+) -> Result<()> {
+    let mut downloader = Downloader::new(reqwest::Client::new(), &config.output);
+    let mut printer = None;
+    if options.format == Format::Ndjson {
+        let (events, mut receiver) = mpsc::unbounded_channel();
+        downloader = downloader.with_events(events);
+        printer = Some(tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                print_json(&event)?;
+            }
+            anyhow::Ok(())
+        }));
+    }
+    let mut queue = DownloadQueue::new(downloader, mirrors)
+        .workers(config.jobs)
+        .per_host(config.per_host)
+        .variant(config.variant)
+        .with_library(library);
+    if let Some(extract) = config.extract() {
+        queue = queue.extract(extract);
+    }
+    if let Some(history) = history {
+        queue = queue.with_history(history);
+    }
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+            }
+        }
+    });
+    let reports = queue.with_cancel(cancel).run(sids).await;
+    // the queue is dropped, so the printer ends after the last event
+    if let Some(printer) = printer {
+        printer.await??;
+    }
+
+    if options.format == Format::Table {
+        for report in &reports {
+            match &report.result {
+                Ok(path) if report.skipped => {
+                    println!("SKIPPED\t{}\t{}", report.sid, path.display())
+                }
+                Ok(path) => println!("OK\t{}\t{}", report.sid, path.display()),
+                Err(e) => println!("FAILED\t{}\t{e:#}", report.sid),
+            }
+        }
+    }
+    let collection = match &options.collection {
+        Some(path) => Some(add_collection(path, name, &reports)?),
+        None => None,
+    };
+    match (options.format, &collection) {
+        (Format::Table, Some(collection)) => {
+            println!("COLLECTION\t{}\t{}", collection.name, collection.count)
+        }
+        (Format::Json, _) => print_json(&json!({
+            "downloads": reports.iter().map(ReportRecord::from).collect::<Vec<_>>(),
+            "collection": collection,
+        }))?,
+        (Format::Ndjson, Some(collection)) => print_json(&json!({
+            "event": "collection",
+            "name": collection.name,
+            "count": collection.count,
+        }))?,
+        _ => {}
+    }
+    let failed = reports
+        .iter()
+        .filter(|report| report.result.is_err())
+        .count();
+    if failed > 0 {
+        bail!("{failed} of {} downloads failed", reports.len());
+    }
+    Ok(())
+}
+
+/// Add the difficulties of downloaded sets to the collection `name`
+/// in the `collection.db` at `path`. Sets that cannot be read are left out.
+fn add_collection(
+    path: &Path,
+    name: String,
+    reports: &[DownloadReport],
+) -> Result<CollectionRecord> {
+    let mut md5s = vec![];
+    for report in reports.iter().filter(|report| !report.skipped) {
+        let Ok(set) = &report.result else {
+            continue;
+        };
+        match beatmap_md5s(set) {
+            Ok(set_md5s) => md5s.extend(set_md5s),
+            Err(e) => {
+                tracing::warn!(sid = report.sid, error = %format!("{e:#}"), "left out of the collection");
+            }
+        }
+    }
+    let count = md5s.len();
+    let mut db = CollectionDb::open(path)?;
+    db.merge(Collection {
+        name: name.clone(),
+        md5s,
+    });
+    db.save(path)?;
+    Ok(CollectionRecord { name, count })
+}
+
+/// Print the entries of `history` matching `args`, or export them as JSON
+/// lines.
+pub fn history(history: &History, args: &HistoryArgs) -> Result<()> {
+    let entries = history.entries().iter().filter(|entry| args.matches(entry));
+    if let Some(path) = &args.export {
+        let mut file = BufWriter::new(File::create(path)?);
+        for entry in entries {
+            serde_json::to_writer(&mut file, entry)?;
+            file.write_all(b"\n")?;
+        }
+        return Ok(file.flush()?);
+    }
+    for entry in entries {
+        let time = OffsetDateTime::from_unix_timestamp(entry.timestamp)?.format(&Rfc3339)?;
+        let result = match entry.outcome {
+            Outcome::Done => entry
+                .path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            Outcome::Failed => entry.error.clone().unwrap_or_default(),
+        };
+        println!(
+            "{time}\t{}\t{:?}\t{:?}\t{}\t{result}",
+            entry.sid,
+            entry.outcome,
+            entry.variant,
+            entry.mirror.as_deref().unwrap_or("-"),
+        );
     }
     Ok(())
 }
@@ -0,0 +1,17 @@
+use std::{fs::File, path::Path, sync::Mutex};
+
+use anyhow::Result;
+use tracing::level_filters::LevelFilter;
+
+/// Write debug logs of requests and responses into `path`, one json object
+/// per line. Logs never go to the terminal, so that the TUI is kept intact.
+pub fn init(path: &Path) -> Result<()> {
+    let file = File::create(path)?;
+    tracing_subscriber::fmt()
+        .json()
+        .with_max_level(LevelFilter::DEBUG)
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .try_init()
+        .map_err(|e| anyhow::anyhow!(e))
+}
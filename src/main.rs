@@ -6,21 +6,33 @@ pub mod ui;
 pub mod update;
 
 pub mod components;
+pub mod config;
 pub mod core;
+pub mod download;
 pub mod info;
+pub mod logging;
+pub mod output;
 
-use std::io;
+use std::{io, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 
 use app::App;
 use clap::Parser;
-use core::api::{Cli, Commands};
+use config::Config;
+use core::{
+    api::{Cli, Commands, ConfigAction, PacksAction},
+    library::Library,
+    mirror::Mirrors,
+};
 use current_locale::current_locale;
+use download::{history::History, queue::DownloadQueue, Downloader};
 use event::{Event, EventHandler};
+use futures::{stream, StreamExt};
 use info::info;
 use ratatui::{backend::CrosstermBackend, Terminal};
 use rust_i18n::set_locale;
+use tokio::sync::mpsc::{self, UnboundedSender};
 use tui::Tui;
 use update::update;
 
@@ -33,25 +45,111 @@ async fn main() -> Result<()> {
     set_locale(current_locale().unwrap_or("zh-CN".to_owned()).as_str());
 
     let cli = Cli::parse();
-    let api = &match cli.download.as_ref().unwrap() {
-        Commands::Download(api) => api.clone(),
+    if cli.debug {
+        logging::init(&cli.log_file)?;
+    }
+    let config_path = cli.config.clone().or_else(Config::default_path);
+    if let Some(Commands::Config {
+        action: ConfigAction::Init { force },
+    }) = cli.download
+    {
+        let path = config_path.context("no config directory, use --config")?;
+        Config::init(&path, force)?;
+        println!("{}", path.display());
+        return Ok(());
+    }
+    let mut config = match &config_path {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    config.merge(&cli);
+    if let Some(options) = cli.download.as_ref().and_then(Commands::download_options) {
+        config.merge_download(options);
+    }
+    config.validate().with_context(|| {
+        match config_path.as_deref().filter(|path| path.exists()) {
+            Some(path) => format!("invalid settings in {} or the flags", path.display()),
+            None => "invalid flags".to_string(),
+        }
+    })?;
+    if let Some(Commands::Config {
+        action: ConfigAction::Validate,
+    }) = cli.download
+    {
+        let path = config_path.context("no config directory, use --config")?;
+        if !path.exists() {
+            bail!("{} does not exist, the defaults are in use", path.display());
+        }
+        println!("OK\t{}", path.display());
+        return Ok(());
+    }
+    if let Some(locale) = &config.locale {
+        set_locale(locale);
+    }
+    if let Some(Commands::Config { .. }) = cli.download {
+        print!("{}", config.to_toml()?);
+        return Ok(());
+    }
+
+    let mirrors = Arc::new(config.mirrors()?);
+    let library = match &config.songs {
+        Some(dir) => Library::load(dir)?,
+        None => Library::default(),
+    };
+    let history = match History::default_path().filter(|_| config.history) {
+        Some(path) => Some(Arc::new(History::open(&path)?)),
+        None => None,
+    };
+    match cli.download {
+        Some(Commands::List(mut args)) => {
+            config.fill_query(&mut args.query);
+            return info(&mirrors, &args.query.to_api(), args.format).await;
+        }
+        Some(Commands::Download(mut args)) => {
+            config.fill_query(&mut args.query);
+            return info::download(mirrors, Arc::new(library), history, &config, &args).await;
+        }
+        Some(Commands::Packs {
+            action: PacksAction::List { kind, format },
+        }) => return info::packs(&mirrors, kind, format).await,
+        Some(Commands::Packs {
+            action: PacksAction::Download(args),
+        }) => {
+            return info::download_packs(mirrors, Arc::new(library), history, &config, &args).await;
+        }
+        Some(Commands::History(args)) => {
+            let history = history.context("download history is disabled or unavailable")?;
+            return info::history(&history, &args);
+        }
+        Some(Commands::Config { .. }) | None => {}
     }
-    .with_cmd("beatmaplist".to_string());
-    // test
-    return info(api).await;
 
     // Create an application.
-    let mut app = App::new();
+    let tick_rate = config.tick_rate;
+    let mut app = App::with_config(config);
+    app.library = Arc::new(library);
+    app.download_history = history;
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stderr());
     let terminal = Terminal::new(backend)?;
-    let events = EventHandler::new(250);
+    let events = EventHandler::new(tick_rate);
     let mut tui = Tui::new(terminal, events);
     tui.init()?;
 
+    let client = reqwest::Client::new();
+
+    let downloads = download_queue(&app, &client, &mirrors, &tui.events.sender());
+
     // Start the main loop.
     while !app.force_quit && app.layer > 0 {
+        spawn_requests(
+            &mut app,
+            &client,
+            &mirrors,
+            &downloads,
+            &tui.events.sender(),
+        );
         // Render the user interface.
         tui.draw(&mut app)?;
         // Handle events.
@@ -60,6 +158,9 @@ async fn main() -> Result<()> {
             Event::Key(key_event) => update(&mut app, key_event),
             Event::Mouse(_) => {}
             Event::Resize(_, _) => {}
+            Event::List(generation, response) => app.receive(generation, response),
+            Event::Detail(set) => app.receive_detail(*set),
+            Event::Download(event) => app.receive_download(event),
         }
     }
 
@@ -67,3 +168,79 @@ async fn main() -> Result<()> {
     tui.exit()?;
     Ok(())
 }
+
+/// Send the requests the app is waiting for in background. Responses come
+/// back as events.
+fn spawn_requests(
+    app: &mut App,
+    client: &reqwest::Client,
+    mirrors: &Arc<Mirrors>,
+    downloads: &DownloadQueue,
+    sender: &UnboundedSender<Event>,
+) {
+    if let Some(api) = app.query.take() {
+        let generation = app.generation;
+        let (client, mirrors, sender) = (client.clone(), mirrors.clone(), sender.clone());
+        tokio::spawn(async move {
+            let response = mirrors.query(&client, &api).await;
+            let response = response.map_err(|e| format!("{e:#}"));
+            let _ = sender.send(Event::List(generation, response));
+        });
+    }
+    if !app.pending_details.is_empty() {
+        let sids = std::mem::take(&mut app.pending_details);
+        let (client, mirrors, sender) = (client.clone(), mirrors.clone(), sender.clone());
+        tokio::spawn(async move {
+            stream::iter(sids)
+                .for_each_concurrent(4, |sid| {
+                    let (client, mirrors, sender) = (&client, &mirrors, &sender);
+                    async move {
+                        if let Ok(set) = mirrors.beatmap_set(client, sid).await {
+                            let _ = sender.send(Event::Detail(Box::new(set)));
+                        }
+                    }
+                })
+                .await;
+        });
+    }
+    if !app.pending_downloads.is_empty() {
+        let sids = std::mem::take(&mut app.pending_downloads);
+        let queue = downloads
+            .clone()
+            .with_library(app.library.clone())
+            .variant(app.download_variant());
+        tokio::spawn(async move { queue.run(sids).await });
+    }
+}
+
+/// The queue of the app, which every batch of downloads is run from, so that
+/// they share the limits of concurrent downloads. Its events come back as
+/// events of the app.
+fn download_queue(
+    app: &App,
+    client: &reqwest::Client,
+    mirrors: &Arc<Mirrors>,
+    sender: &UnboundedSender<Event>,
+) -> DownloadQueue {
+    let (events, mut receiver) = mpsc::unbounded_channel();
+    let sender = sender.clone();
+    tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            let _ = sender.send(Event::Download(event));
+        }
+    });
+    let mut queue = DownloadQueue::new(
+        Downloader::new(client.clone(), &app.config.output).with_events(events),
+        mirrors.clone(),
+    )
+    .workers(app.config.jobs)
+    .per_host(app.config.per_host)
+    .with_cancel(app.cancel.clone());
+    if let Some(extract) = app.config.extract() {
+        queue = queue.extract(extract);
+    }
+    if let Some(history) = &app.download_history {
+        queue = queue.with_history(history.clone());
+    }
+    queue
+}
@@ -0,0 +1,197 @@
+//! Machine-readable output of the command line. The records here are the
+//! documented schema: fields are only ever added, never renamed or removed.
+
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    core::{
+        api::{names, GameModes},
+        beatmap::{BeatmapSet, RankedStatus},
+        pack::Pack,
+    },
+    download::{DownloadReport, ErrorKind},
+};
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Tab separated lines for people.
+    #[default]
+    Table,
+    /// One JSON document at the end.
+    Json,
+    /// One JSON object per line as soon as it is known.
+    Ndjson,
+}
+
+/// A beatmap set of the `list` command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SetRecord<'a> {
+    pub sid: u32,
+    pub artist: &'a str,
+    pub artist_unicode: Option<&'a str>,
+    pub title: &'a str,
+    pub title_unicode: Option<&'a str>,
+    pub creator: &'a str,
+    /// `graveyard`, `wip`, `pending`, `ranked`, `approved`, `qualified`,
+    /// `loved` or `unknown`.
+    pub status: &'static str,
+    #[serde(with = "names")]
+    pub modes: GameModes,
+    /// Unix timestamp of the ranking, or the last update.
+    pub date: i64,
+    pub bpm: Option<f32>,
+    pub play_count: u64,
+    pub favourite_count: u64,
+}
+
+impl<'a> From<&'a BeatmapSet> for SetRecord<'a> {
+    fn from(set: &'a BeatmapSet) -> Self {
+        let non_empty = |s: &'a str| (!s.is_empty()).then_some(s);
+        Self {
+            sid: set.sid,
+            artist: &set.artist,
+            artist_unicode: non_empty(&set.artist_unicode),
+            title: &set.title,
+            title_unicode: non_empty(&set.title_unicode),
+            creator: &set.creator,
+            status: match set.approved {
+                RankedStatus::Graveyard => "graveyard",
+                RankedStatus::Wip => "wip",
+                RankedStatus::Pending => "pending",
+                RankedStatus::Ranked => "ranked",
+                RankedStatus::Approved => "approved",
+                RankedStatus::Qualified => "qualified",
+                RankedStatus::Loved => "loved",
+                RankedStatus::Unknown(_) => "unknown",
+            },
+            modes: GameModes::from_bits_truncate(set.modes.into()),
+            date: set.date(),
+            bpm: set.bpm,
+            play_count: set.play_count,
+            favourite_count: set.favourite_count,
+        }
+    }
+}
+
+/// A beatmap pack of the `packs list` command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackRecord<'a> {
+    /// Like `S1000`.
+    pub tag: &'a str,
+    pub name: &'a str,
+    pub author: &'a str,
+    /// Unix timestamp of the release.
+    pub date: Option<i64>,
+}
+
+impl<'a> From<&'a Pack> for PackRecord<'a> {
+    fn from(pack: &'a Pack) -> Self {
+        Self {
+            tag: &pack.tag,
+            name: &pack.name,
+            author: &pack.author,
+            date: pack.date,
+        }
+    }
+}
+
+/// Result of one set of the `download` command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportRecord<'a> {
+    pub sid: u32,
+    /// `done`, `skipped` or `failed`.
+    pub outcome: &'static str,
+    /// The `.osz`, or the folder if extracted.
+    pub path: Option<&'a Path>,
+    pub kind: Option<ErrorKind>,
+    pub error: Option<String>,
+}
+
+impl<'a> From<&'a DownloadReport> for ReportRecord<'a> {
+    fn from(report: &'a DownloadReport) -> Self {
+        match &report.result {
+            Ok(path) => Self {
+                sid: report.sid,
+                outcome: if report.skipped { "skipped" } else { "done" },
+                path: Some(path),
+                kind: None,
+                error: None,
+            },
+            Err(e) => Self {
+                sid: report.sid,
+                outcome: "failed",
+                path: None,
+                kind: Some(ErrorKind::of(e)),
+                error: Some(format!("{e:#}")),
+            },
+        }
+    }
+}
+
+/// The collection the downloaded difficulties are added to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollectionRecord {
+    pub name: String,
+    /// Number of difficulties added.
+    pub count: usize,
+}
+
+/// Write `value` as one line of JSON to stdout.
+pub fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, value)?;
+    stdout.write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::beatmap::BeatmapList;
+
+    #[test]
+    fn test_set_record() {
+        let list: BeatmapList =
+            serde_json::from_str(include_str!("../test/fixtures/beatmaplist.json")).unwrap();
+        let record = SetRecord::from(&list.data[0]);
+        let json = serde_json::to_value(&record).unwrap();
+        for key in [
+            "sid",
+            "artist",
+            "artist_unicode",
+            "title",
+            "title_unicode",
+            "creator",
+            "status",
+            "modes",
+            "date",
+            "bpm",
+            "play_count",
+            "favourite_count",
+        ] {
+            assert!(json.get(key).is_some(), "{key}");
+        }
+        assert_eq!(json["sid"], list.data[0].sid);
+    }
+
+    #[test]
+    fn test_report_record() {
+        let report = DownloadReport {
+            sid: 1,
+            result: Err(crate::download::Cancelled.into()),
+            skipped: false,
+        };
+        assert_eq!(
+            serde_json::to_string(&ReportRecord::from(&report)).unwrap(),
+            r#"{"sid":1,"outcome":"failed","path":null,"kind":"cancelled","error":"cancelled"}"#
+        );
+    }
+}
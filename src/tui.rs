@@ -58,6 +58,8 @@ impl<B: Backend> Tui<B> {
         self.terminal.draw(|frame| match app.current_page() {
             Page::Main => ui::render_main(app, frame),
             Page::Help => ui::render_help(app, frame),
+            Page::Results => ui::render_results(app, frame),
+            Page::Downloads => ui::render_downloads(app, frame),
         })?;
         Ok(())
     }
@@ -1,17 +1,23 @@
 use std::collections::HashMap;
 
+use crossterm::event::KeyCode;
 use ratatui::{
-    layout::{Constraint, Direction},
+    layout::{Constraint, Direction, Rect},
     prelude::{Alignment, Frame, Layout},
-    style::{Color, Style, Stylize},
+    style::{Color, Modifier, Style, Stylize},
     text::{Line, Span, Text},
     widgets::{
         calendar::{CalendarEventStore, Monthly},
-        Block, BorderType, Borders, Paragraph, Widget,
+        Block, BorderType, Borders, Gauge, HighlightSpacing, Paragraph, Row, Table, TableState,
+        Widget,
     },
 };
 
-use crate::app::{App, DEFAULT_BLOCK};
+use crate::{
+    app::{App, DownloadState, DEFAULT_BLOCK},
+    config::Key,
+    core::api::{names, GameModes},
+};
 
 /// Render main widget.
 pub fn render_main(app: &mut App, f: &mut Frame) {
@@ -28,7 +34,12 @@ pub fn render_main(app: &mut App, f: &mut Frame) {
             .split(v_layouts[0]),
         Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+            .constraints([
+                Constraint::Ratio(1, 4),
+                Constraint::Ratio(1, 4),
+                Constraint::Ratio(1, 4),
+                Constraint::Ratio(1, 4),
+            ])
             .split(v_layouts[1]),
     ];
 
@@ -72,11 +83,20 @@ pub fn render_main(app: &mut App, f: &mut Frame) {
             app.text2
                 .set_placeholder_style(Style::default().fg(app.session_color(1)));
             app.text2.set_placeholder_text(t!("input.name"));
-            app.text2.set_block(
-                DEFAULT_BLOCK
-                    .title(t!("mode.search"))
-                    .style(Style::default().fg(app.session_color(1))),
-            );
+            let block = DEFAULT_BLOCK
+                .clone()
+                .style(Style::default().fg(app.session_color(1)));
+            match &app.search_error {
+                Some(error) => {
+                    app.text2.set_style(Style::default().fg(Color::LightRed));
+                    app.text2
+                        .set_block(block.title(t!("error.search", error = error)));
+                }
+                None => {
+                    app.text2.set_style(Style::default());
+                    app.text2.set_block(block.title(t!("mode.search")));
+                }
+            }
             f.render_widget(app.text2.widget(), layouts[0][1]);
         }
         _ => unreachable!("mode numeber exceeded."),
@@ -101,17 +121,252 @@ pub fn render_main(app: &mut App, f: &mut Frame) {
             .style(Style::default().fg(app.session_color(3))),
         layouts[1][1],
     );
+
+    // session 4
+    app.variant.render(
+        f,
+        DEFAULT_BLOCK
+            .clone()
+            .title(t!("variant"))
+            .style(Style::default().fg(app.session_color(4))),
+        layouts[1][2],
+    );
+
+    // session 5
+    let invalid = app.build_filters().err();
+    let title = if invalid.is_some() {
+        t!("error.range")
+    } else {
+        t!("filters")
+    };
+    app.filters.render(
+        f,
+        DEFAULT_BLOCK
+            .clone()
+            .title(title)
+            .style(Style::default().fg(app.session_color(5))),
+        layouts[1][3],
+        invalid,
+    );
+}
+
+/// Render results widget.
+pub fn render_results(app: &mut App, f: &mut Frame) {
+    let (title, color) = if let Some(e) = &app.error {
+        (t!("error.request", error = e), Color::LightRed)
+    } else if app.loading {
+        (t!("results.loading"), Color::Yellow)
+    } else if app.sets.is_empty() {
+        (t!("results.empty"), Color::White)
+    } else {
+        let more = if app.has_more { "+" } else { "" };
+        (
+            t!(
+                "results.count",
+                count = app.sets.len(),
+                more = more,
+                selected = app.results.selected().count()
+            ),
+            Color::Green,
+        )
+    };
+
+    let header = Row::new([
+        t!("results.title"),
+        t!("results.artist"),
+        t!("results.creator"),
+        t!("results.status"),
+        t!("results.modes"),
+        t!("results.stars"),
+        t!("results.length"),
+        "BPM".to_string(),
+        t!("results.favourites"),
+    ])
+    .style(Style::default().bold().fg(Color::Yellow));
+    let rows = app.sets.iter().enumerate().map(|(i, set)| {
+        let modes = names::names(&GameModes::from_bits_truncate(set.modes.into())).join(",");
+        let stars = set
+            .star_range()
+            .map(|(min, max)| format!("{min:.2}-{max:.2}"))
+            .unwrap_or("-".to_string());
+        let length = set
+            .length()
+            .map(|len| format!("{}:{:02}", len / 60, len % 60))
+            .unwrap_or("-".to_string());
+        let bpm = set
+            .bpm
+            .map(|bpm| format!("{bpm:.0}"))
+            .unwrap_or("-".to_string());
+        Row::new([
+            set.display_title().to_string(),
+            set.display_artist().to_string(),
+            set.creator.clone(),
+            set.approved.to_string(),
+            modes,
+            stars,
+            length,
+            bpm,
+            set.favourite_count.to_string(),
+        ])
+        .style(if app.results.is_selected(i) {
+            Style::default().fg(Color::Green)
+        } else if app.owns(set.sid) {
+            Style::default().fg(Color::DarkGray)
+        } else {
+            Style::default().fg(Color::White)
+        })
+    });
+    let widths = [
+        Constraint::Ratio(3, 12),
+        Constraint::Ratio(2, 12),
+        Constraint::Ratio(1, 12),
+        Constraint::Length(9),
+        Constraint::Ratio(1, 12),
+        Constraint::Length(9),
+        Constraint::Length(6),
+        Constraint::Length(4),
+        Constraint::Length(6),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .highlight_spacing(HighlightSpacing::Always)
+        .highlight_symbol("> ")
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .block(
+            DEFAULT_BLOCK
+                .clone()
+                .title(title)
+                .style(Style::default().fg(color)),
+        );
+    f.render_stateful_widget(
+        table,
+        f.size(),
+        &mut TableState::default().with_selected(Some(app.results.cursor)),
+    );
+}
+
+/// Render downloads widget.
+pub fn render_downloads(app: &mut App, f: &mut Frame) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)])
+        .split(f.size());
+
+    let total = app.downloads.len();
+    let count = |state| {
+        app.downloads
+            .iter()
+            .filter(|item| item.state == state)
+            .count()
+    };
+    let done = count(DownloadState::Done) + count(DownloadState::Skipped);
+    let failed = count(DownloadState::Failed);
+    let ratio = if total == 0 {
+        0.0
+    } else {
+        (done + failed) as f64 / total as f64
+    };
+    f.render_widget(
+        Gauge::default()
+            .block(DEFAULT_BLOCK.clone().title(t!("downloads")))
+            .gauge_style(Style::default().fg(Color::Green))
+            .ratio(ratio)
+            .label(t!(
+                "downloads.summary",
+                done = done,
+                failed = failed,
+                total = total
+            )),
+        layout[0],
+    );
+
+    let block = DEFAULT_BLOCK
+        .clone()
+        .title(t!("downloads.help", key = app.config.keys.retry));
+    let area = block.inner(layout[1]);
+    f.render_widget(block, layout[1]);
+    let rows = area.height as usize;
+    let start = (app.downloads_cursor + 1).saturating_sub(rows);
+    for (i, item) in app.downloads.iter().enumerate().skip(start).take(rows) {
+        let (state, color) = match item.state {
+            DownloadState::Queued => (t!("downloads.queued"), Color::White),
+            DownloadState::Downloading => (t!("downloads.downloading"), Color::Cyan),
+            DownloadState::Verifying => (t!("downloads.verifying"), Color::Yellow),
+            DownloadState::Done => (t!("downloads.done"), Color::Green),
+            DownloadState::Skipped => (t!("downloads.skipped"), Color::DarkGray),
+            DownloadState::Failed => (t!("downloads.failed"), Color::LightRed),
+        };
+        let mut label = format!(
+            "{}{} {}  [{state}]  {}",
+            if i == app.downloads_cursor {
+                "> "
+            } else {
+                "  "
+            },
+            item.sid,
+            item.title,
+            format_bytes(item.downloaded)
+        );
+        if let Some(total) = item.total {
+            label += &format!("/{}", format_bytes(total));
+        }
+        if item.state == DownloadState::Downloading {
+            label += &format!("  {}/s", format_bytes(item.speed as u64));
+            if let Some(eta) = item.eta() {
+                label += &format!("  ETA {}:{:02}", eta / 60, eta % 60);
+            }
+        }
+        if let Some(info) = &item.info {
+            label += &format!(
+                "  {}",
+                t!("downloads.difficulties", count = info.beatmaps.len())
+            );
+        }
+        if let Some(e) = &item.error {
+            label += &format!("  {e}");
+        }
+        let row = Rect::new(area.x, area.y + (i - start) as u16, area.width, 1);
+        f.render_widget(
+            Gauge::default()
+                .gauge_style(Style::default().fg(color).bg(Color::Black))
+                .ratio(item.ratio())
+                .label(Span::styled(label, Style::default().fg(Color::White)))
+                .use_unicode(true),
+            row,
+        );
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
 }
 
 /// Render help widget.
-pub fn render_help(_: &mut App, f: &mut Frame) {
+pub fn render_help(app: &mut App, f: &mut Frame) {
+    let keys = &app.config.keys;
+    let select = if keys.select == Key(KeyCode::Char(' ')) {
+        t!("space")
+    } else {
+        keys.select.to_string()
+    };
     let mut help_message = Text::raw(t!(
         "help.content",
         updown = "↑↓",
         leftright = "←→",
-        select = t!("space"),
-        quit = "ESC",
-        ok = "Enter",
+        select = select,
+        quit = keys.back,
+        ok = keys.confirm,
     ));
     help_message.extend(vec![Line::from(vec![
         Span::styled(t!("help.select"), Style::default().fg(Color::Green)),
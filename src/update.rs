@@ -9,19 +9,26 @@ pub fn update(app: &mut App, key_event: KeyEvent) {
     {
         return app.force_quit();
     }
-    match key_event.code {
-        KeyCode::Esc => app.go_back(),
-        KeyCode::Left => app.prev_session(),
-        KeyCode::Right => app.next_session(),
-        _ => match app.page {
-            Page::Main => update_main_page(app, key_event),
-            Page::Help => app.help(),
-        },
-    };
+    if app.config.keys.back.matches(key_event.code) {
+        return app.go_back();
+    }
+    match app.page {
+        Page::Main => update_main_page(app, key_event),
+        Page::Results => update_results_page(app, key_event),
+        Page::Downloads => update_downloads_page(app, key_event),
+        Page::Help => app.help(),
+    }
 }
 
 pub fn update_main_page(app: &mut App, key_event: KeyEvent) {
     let key = key_event.code;
+    let keys = &app.config.keys;
+    match key {
+        KeyCode::Left => return app.prev_session(),
+        KeyCode::Right => return app.next_session(),
+        key if keys.confirm.matches(key) => return app.query(),
+        _ => {}
+    }
     match app.session {
         0 => match key {
             KeyCode::Up => app.mode.prev(),
@@ -34,11 +41,14 @@ pub fn update_main_page(app: &mut App, key_event: KeyEvent) {
                 app.text.input(key_event);
             }
             3 => {
-                app.text2.input(key_event);
+                if app.text2.input(key_event) {
+                    app.validate_search();
+                }
             }
             _ => unreachable!("mode numeber exceeded."),
         },
         2 | 3 => {
+            let select = app.config.keys.select;
             let multilist = if app.session == 2 {
                 &mut app.item
             } else {
@@ -47,16 +57,67 @@ pub fn update_main_page(app: &mut App, key_event: KeyEvent) {
             match key {
                 KeyCode::Down => multilist.next(),
                 KeyCode::Up => multilist.prev(),
-                KeyCode::Char(' ') => {
+                key if select.matches(key) => {
                     multilist.select_cursor();
                 }
                 _ => app.help(),
             };
         }
+        4 => match key {
+            KeyCode::Up => app.variant.prev(),
+            KeyCode::Down => app.variant.next(),
+            _ => app.help(),
+        },
+        5 => match key {
+            KeyCode::Up => app.filters.prev(),
+            KeyCode::Down => app.filters.next(),
+            key => {
+                if !app.filters.input(key) {
+                    app.help();
+                }
+            }
+        },
         _ => unreachable!("session numeber exceeded."),
     }
 }
 
+pub fn update_results_page(app: &mut App, key_event: KeyEvent) {
+    if app.results.is_empty() {
+        return;
+    }
+    let keys = &app.config.keys;
+    match key_event.code {
+        KeyCode::Up => app.results.prev(),
+        KeyCode::Down => {
+            // load the next page when scrolling past the end
+            if app.results.cursor + 1 == app.results.len() && app.has_more {
+                app.load_more();
+            } else {
+                app.results.next();
+            }
+        }
+        key if keys.load_more.matches(key) => app.load_more(),
+        key if keys.confirm.matches(key) => app.download_selected(),
+        key if keys.select.matches(key) => {
+            app.results.select_cursor();
+        }
+        _ => {}
+    }
+}
+
+pub fn update_downloads_page(app: &mut App, key_event: KeyEvent) {
+    let len = app.downloads.len();
+    if len == 0 {
+        return;
+    }
+    match key_event.code {
+        KeyCode::Up => app.downloads_cursor = (app.downloads_cursor + len - 1) % len,
+        KeyCode::Down => app.downloads_cursor = (app.downloads_cursor + 1) % len,
+        key if app.config.keys.retry.matches(key) => app.retry_failed(),
+        _ => {}
+    }
+}
+
 pub fn update_calendar(app: &mut App, key_event: KeyEvent) {
     let day = if key_event.modifiers == KeyModifiers::SHIFT {
         30